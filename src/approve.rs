@@ -0,0 +1,384 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use notify_rust::Notification;
+
+use crate::error::{Error, Result};
+use crate::peer::Peer;
+use crate::session::SignPurpose;
+
+/// user decision on a sign request
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Decision {
+    Approved,
+    Denied,
+    TimedOut,
+
+    /// client went away before user decided
+    Cancelled,
+}
+
+/// how often blocking approvers check for cancellation
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// cancellation flag of a pending approval, shared with the blocking approver
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// cancel when returned guard is dropped, e.g. with the future waiting on approval
+    pub fn guard(&self) -> CancelGuard {
+        CancelGuard(self.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct CancelGuard(Cancel);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// sign request presented to user
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ApprovalRequest {
+    pub key_name: String,
+    pub fingerprint: String,
+
+    /// destination host of the connection, from session bind
+    pub destination: Option<String>,
+
+    /// decoded data being signed, `None` for unknown data
+    pub purpose: Option<SignPurpose>,
+
+    /// process asking for signature
+    pub peer: Option<Peer>,
+}
+
+impl ApprovalRequest {
+    /// one line description of request
+    pub fn body(&self) -> String {
+        let mut body = match &self.peer {
+            Some(peer) => format!("{} asks to sign ", peer),
+            None => "sign ".to_owned(),
+        };
+        match &self.purpose {
+            Some(purpose) => body.push_str(&purpose.to_string()),
+            None => body.push_str("data"),
+        }
+        body.push_str(&format!(" with key {}", self.key_name));
+        if let Some(destination) = &self.destination {
+            body.push_str(&format!(" for {}", destination));
+        }
+
+        body
+    }
+}
+
+/// approval backend asked before signing.
+///
+/// `approve` blocks until user decides, it runs on a blocking thread and should
+/// return `Decision::Cancelled` soon after `cancel` is set.
+pub trait Approver: Debug + Send + Sync {
+    fn approve(&self, request: &ApprovalRequest, cancel: &Cancel) -> Result<Decision>;
+}
+
+/// build approver from backend spec: `notify`, `terminal`, `allow`, `deny` or `command:<cmd>`
+pub fn from_spec(spec: &str, timeout: Duration) -> Result<Arc<dyn Approver>> {
+    let approver: Arc<dyn Approver> = match spec {
+        "notify" => Arc::new(Notify { timeout }),
+        "terminal" => Arc::new(Terminal),
+        "allow" => Arc::new(AllowAll),
+        "deny" => Arc::new(DenyAll),
+        _ => match spec.strip_prefix("command:") {
+            Some(command) => Arc::new(External {
+                command: command.to_owned(),
+                timeout,
+            }),
+            None => return Err(Error::UnknownApprover(spec.to_owned())),
+        },
+    };
+
+    Ok(approver)
+}
+
+/// desktop notification with approve and reject actions.
+///
+/// a cancelled notification is left to expire by its own timeout, its answer is ignored.
+#[derive(Debug)]
+pub struct Notify {
+    pub timeout: Duration,
+}
+
+impl Approver for Notify {
+    fn approve(&self, request: &ApprovalRequest, cancel: &Cancel) -> Result<Decision> {
+        let mut notification = Notification::new();
+        notification
+            .summary("KeyD sign request")
+            .body(&request.body())
+            .appname("KeyD")
+            .action("approve", "approve")
+            .action("reject", "reject")
+            .timeout(self.timeout.as_millis() as i32);
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || match notification.show() {
+            Ok(handle) => handle.wait_for_action(|action| {
+                tx.send(Ok(action.to_owned())).ok();
+            }),
+            Err(e) => {
+                tx.send(Err(Error::Approval(e.to_string()))).ok();
+            }
+        });
+
+        loop {
+            match rx.recv_timeout(CANCEL_POLL) {
+                Ok(action) => {
+                    return Ok(match &*action? {
+                        "approve" => Decision::Approved,
+                        "__closed" => Decision::TimedOut,
+                        _ => Decision::Denied,
+                    });
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) if cancel.is_cancelled() => {
+                    return Ok(Decision::Cancelled);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(Decision::Denied),
+            }
+        }
+    }
+}
+
+/// prompt on controlling terminal of agent
+#[derive(Debug)]
+pub struct Terminal;
+
+impl Approver for Terminal {
+    fn approve(&self, request: &ApprovalRequest, cancel: &Cancel) -> Result<Decision> {
+        let tty = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")?;
+
+        let mut writer = &tty;
+        write!(writer, "keyd: {}, approve? [y/N] ", request.body())?;
+        writer.flush()?;
+
+        let mut fd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        while unsafe { libc::poll(&mut fd, 1, CANCEL_POLL.as_millis() as i32) } == 0 {
+            if cancel.is_cancelled() {
+                writeln!(writer, "cancelled")?;
+                return Ok(Decision::Cancelled);
+            }
+        }
+
+        let mut answer = String::new();
+        BufReader::new(&tty).read_line(&mut answer)?;
+
+        Ok(match answer.trim() {
+            "y" | "Y" | "yes" => Decision::Approved,
+            _ => Decision::Denied,
+        })
+    }
+}
+
+/// approve every request without asking
+#[derive(Debug)]
+pub struct AllowAll;
+
+impl Approver for AllowAll {
+    fn approve(&self, _request: &ApprovalRequest, _cancel: &Cancel) -> Result<Decision> {
+        Ok(Decision::Approved)
+    }
+}
+
+/// deny every request without asking
+#[derive(Debug)]
+pub struct DenyAll;
+
+impl Approver for DenyAll {
+    fn approve(&self, _request: &ApprovalRequest, _cancel: &Cancel) -> Result<Decision> {
+        Ok(Decision::Denied)
+    }
+}
+
+/// run shell command, exit status 0 approves.
+///
+/// request is passed by `KEYD_KEY_NAME`, `KEYD_FINGERPRINT`, `KEYD_DESTINATION`,
+/// `KEYD_PURPOSE`, `KEYD_PEER_PID`, `KEYD_PEER_UID`, `KEYD_PEER_COMMAND`, `KEYD_PEER_EXE`,
+/// `KEYD_PEER_CMDLINE` and `KEYD_BODY` environment variables, unknown ones are empty.
+#[derive(Debug)]
+pub struct External {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl Approver for External {
+    fn approve(&self, request: &ApprovalRequest, cancel: &Cancel) -> Result<Decision> {
+        let peer = request.peer.as_ref();
+        let mut child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("KEYD_KEY_NAME", &request.key_name)
+            .env("KEYD_FINGERPRINT", &request.fingerprint)
+            .env(
+                "KEYD_DESTINATION",
+                request.destination.as_deref().unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PURPOSE",
+                request
+                    .purpose
+                    .as_ref()
+                    .map(|it| it.to_string())
+                    .unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PEER_PID",
+                peer.and_then(|it| it.pid)
+                    .map(|it| it.to_string())
+                    .unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PEER_UID",
+                peer.map(|it| it.uid.to_string()).unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PEER_COMMAND",
+                peer.and_then(|it| it.command.as_deref())
+                    .unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PEER_EXE",
+                peer.and_then(|it| it.exe_path()).unwrap_or_default(),
+            )
+            .env(
+                "KEYD_PEER_CMDLINE",
+                peer.and_then(|it| it.cmdline.as_deref())
+                    .unwrap_or_default(),
+            )
+            .env("KEYD_BODY", request.body())
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(if status.success() {
+                    Decision::Approved
+                } else {
+                    Decision::Denied
+                });
+            }
+
+            if cancel.is_cancelled() {
+                child.kill().ok();
+                child.wait().ok();
+                return Ok(Decision::Cancelled);
+            }
+
+            if Instant::now() >= deadline {
+                child.kill().ok();
+                child.wait().ok();
+                return Ok(Decision::TimedOut);
+            }
+
+            std::thread::sleep(CANCEL_POLL);
+        }
+    }
+}
+
+/// replay preset decisions and record requests, for tests.
+///
+/// deny once decisions run out.
+#[derive(Debug, Default)]
+pub struct Scripted {
+    decisions: Mutex<VecDeque<Decision>>,
+    requests: Mutex<Vec<ApprovalRequest>>,
+}
+
+impl Scripted {
+    pub fn new(decisions: impl IntoIterator<Item = Decision>) -> Scripted {
+        Scripted {
+            decisions: Mutex::new(decisions.into_iter().collect()),
+            requests: Default::default(),
+        }
+    }
+
+    /// requests asked so far
+    pub fn requests(&self) -> Vec<ApprovalRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Approver for Scripted {
+    fn approve(&self, request: &ApprovalRequest, _cancel: &Cancel) -> Result<Decision> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        Ok(self
+            .decisions
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(Decision::Denied))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::approve::{ApprovalRequest, Approver, Cancel, Decision, External};
+
+    #[test]
+    fn cancel_external() {
+        let approver = External {
+            command: "sleep 10".into(),
+            timeout: Duration::from_secs(20),
+        };
+        let request = ApprovalRequest {
+            key_name: "key".into(),
+            fingerprint: "SHA256:key".into(),
+            destination: None,
+            purpose: None,
+            peer: None,
+        };
+
+        let cancel = Cancel::default();
+        {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                drop(cancel.guard());
+            });
+        }
+
+        let start = Instant::now();
+        assert_eq!(
+            approver.approve(&request, &cancel).unwrap(),
+            Decision::Cancelled
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,198 @@
+use bytes::{Buf, Bytes, BytesMut};
+use libsshkey::key::HashType;
+use libsshkey::SSHBuffer;
+
+/// suffix of OpenSSH certificate key types
+pub const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+
+/// OpenSSH certificate, see PROTOCOL.certkeys in OpenSSH
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Certificate {
+    /// whole certificate blob, as presented to server
+    pub blob: Bytes,
+
+    /// certificate key type, e.g. `ssh-ed25519-cert-v01@openssh.com`
+    pub cert_type: String,
+
+    /// public key fields in certificate order
+    pub fields: Vec<Bytes>,
+
+    pub serial: u64,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+
+    /// public key blob of signing CA
+    pub signature_key: Bytes,
+}
+
+impl Certificate {
+    pub fn parse(blob: impl AsRef<[u8]>) -> anyhow::Result<Certificate> {
+        let blob = Bytes::copy_from_slice(blob.as_ref());
+        let mut buf = SSHBuffer::from_bytes_mut(BytesMut::from(&*blob))?;
+
+        let cert_type = String::from_utf8(buf.get_string()?.to_vec())?;
+        let field_count = match plain_type(&cert_type) {
+            Some("ssh-rsa") => 2,
+            Some("ssh-dss") => 4,
+            Some("ssh-ed25519") => 1,
+            Some(ty) if ty.starts_with("ecdsa-sha2-") => 2,
+            _ => anyhow::bail!("unsupported certificate type: {}", cert_type),
+        };
+
+        let _nonce = buf.get_string()?;
+        let fields = (0..field_count)
+            .map(|_| buf.get_string())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if buf.len() < 12 {
+            anyhow::bail!("truncated certificate");
+        }
+        let serial = buf.get_u64();
+        let _ty = buf.get_u32();
+        let key_id = String::from_utf8(buf.get_string()?.to_vec())?;
+
+        let mut principals = Vec::new();
+        let mut packed = SSHBuffer::from_bytes_mut(BytesMut::from(&*buf.get_string()?))?;
+        while !packed.is_empty() {
+            principals.push(String::from_utf8(packed.get_string()?.to_vec())?);
+        }
+
+        if buf.len() < 16 {
+            anyhow::bail!("truncated certificate");
+        }
+        let valid_after = buf.get_u64();
+        let valid_before = buf.get_u64();
+
+        let _critical_options = buf.get_string()?;
+        let _extensions = buf.get_string()?;
+        let _reserved = buf.get_string()?;
+        let signature_key = buf.get_string()?;
+
+        Ok(Certificate {
+            blob,
+            cert_type,
+            fields,
+            serial,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            signature_key,
+        })
+    }
+
+    /// key type without certificate suffix, e.g. `ssh-ed25519`
+    pub fn key_type(&self) -> &str {
+        plain_type(&self.cert_type).expect("checked when parse")
+    }
+
+    /// public key blob of certified key
+    pub fn public_blob(&self) -> anyhow::Result<Vec<u8>> {
+        let mut buf = SSHBuffer::empty()?;
+        buf.put_string(self.key_type())?;
+        for field in &self.fields {
+            buf.put_string(&**field)?;
+        }
+
+        Ok(buf.to_vec())
+    }
+
+    /// SHA256 fingerprint of certified key
+    pub fn fingerprint(&self) -> anyhow::Result<String> {
+        let key = libsshkey::key::parse_public_blob(Bytes::from(self.public_blob()?))?;
+
+        Ok(key.fingerprint(HashType::SHA256)?)
+    }
+
+    /// whether certificate is valid at unix time `now`
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.valid_after <= now && now < self.valid_before
+    }
+
+    /// build plain private key blob from certificate and private fields of a
+    /// certificate add identity request, which omit public parts carried by certificate.
+    pub fn private_blob(&self, private: &[Bytes], comment: &[u8]) -> anyhow::Result<BytesMut> {
+        let mut buf = SSHBuffer::empty()?;
+        buf.put_string(self.key_type())?;
+
+        match self.key_type() {
+            // certificate carries e, n while private key blob starts with n, e
+            "ssh-rsa" => {
+                buf.put_string(&*self.fields[1])?;
+                buf.put_string(&*self.fields[0])?;
+            }
+            "ssh-ed25519" => {}
+            _ => {
+                for field in &self.fields {
+                    buf.put_string(&**field)?;
+                }
+            }
+        }
+
+        for field in private {
+            buf.put_string(&**field)?;
+        }
+        buf.put_string(comment)?;
+
+        Ok(BytesMut::from(&*buf.to_vec()))
+    }
+}
+
+/// strip certificate suffix off `cert_type`, `None` if not a certificate type
+pub fn plain_type(cert_type: &str) -> Option<&str> {
+    cert_type.strip_suffix(CERT_SUFFIX)
+}
+
+/// number of private fields following certificate blob in add identity request
+pub fn private_field_count(cert_type: &str) -> Option<usize> {
+    match plain_type(cert_type)? {
+        // d, iqmp, p, q
+        "ssh-rsa" => Some(4),
+        // x
+        "ssh-dss" => Some(1),
+        // public key, private key
+        "ssh-ed25519" => Some(2),
+        // private scalar
+        ty if ty.starts_with("ecdsa-sha2-") => Some(1),
+        _ => None,
+    }
+}
+
+/// parse authorized_keys style certificate line, `<type> <base64> [comment]`
+pub fn parse_cert_line(line: impl AsRef<str>) -> anyhow::Result<Certificate> {
+    let mut parts = line.as_ref().split_whitespace();
+    let _ty = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty certificate"))?;
+    let encoded = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("missing certificate blob"))?;
+
+    Certificate::parse(openssl::base64::decode_block(encoded)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cert::parse_cert_line;
+
+    const CERT: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIB8NVUOXXtCQZpzOwbPL2uFYplTQO2Myy0Un5fOJLTdFAAAAIL5MjmQ+RJ8Jvz9Cx+vqwddo0xd01zqUqVKOMdsRgXiIAAAAAAAAAAAAAAABAAAADWFsaWNlQGV4YW1wbGUAAAATAAAABWFsaWNlAAAABmRlcGxveQAAAABpVbkAAAAAAGs27IAAAAAAAAAAggAAABVwZXJtaXQtWDExLWZvcndhcmRpbmcAAAAAAAAAF3Blcm1pdC1hZ2VudC1mb3J3YXJkaW5nAAAAAAAAABZwZXJtaXQtcG9ydC1mb3J3YXJkaW5nAAAAAAAAAApwZXJtaXQtcHR5AAAAAAAAAA5wZXJtaXQtdXNlci1yYwAAAAAAAAAAAAAAMwAAAAtzc2gtZWQyNTUxOQAAACAhNhQzrgBrPM6XktrhryglZfl4ZYzIV59mQXXs/i73vgAAAFMAAAALc3NoLWVkMjU1MTkAAABAbSSf5w4c4twvtpOWLtL77HVnyMV2h/kGdx+wRp6UDZ9B7188SLWHCehrma1/iXWtHfH0GfrM7qigqC3HmD3YDQ== user";
+    const PUBLIC: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIL5MjmQ+RJ8Jvz9Cx+vqwddo0xd01zqUqVKOMdsRgXiI";
+
+    #[test]
+    fn parse_ed25519_cert() {
+        let cert = parse_cert_line(CERT).unwrap();
+
+        assert_eq!(cert.key_type(), "ssh-ed25519");
+        assert_eq!(cert.key_id, "alice@example");
+        assert_eq!(cert.principals, vec!["alice", "deploy"]);
+        assert_eq!(cert.valid_after, 1767225600);
+        assert_eq!(cert.valid_before, 1798761600);
+        assert!(cert.is_valid_at(1767225600));
+        assert!(!cert.is_valid_at(1798761600));
+
+        let public = openssl::base64::decode_block(PUBLIC).unwrap();
+        assert_eq!(cert.public_blob().unwrap(), public);
+    }
+}
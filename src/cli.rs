@@ -1,27 +1,237 @@
-use std::io::Read;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use libsshkey::key::parse_private_pem;
-use tracing::{error, info};
+use libsshkey::key::HashType;
+use tracing::{error, info, warn};
 
 use keyd::agent::KeyDAgent;
-use keyd::keyd::KeyD;
+use keyd::approve;
+use keyd::client::AgentClient;
+use keyd::config::Config;
+use keyd::control::{self, call_keyd, AgentStatus, CertInfo, ControlClient, KeyInfo, PolicyInfo};
+use keyd::error::Error;
+use keyd::keyd::{unix_now, KeyD};
+use keyd::socket;
+use keyd::store::models::{AuditEvent, KeyGroup, Policy};
+use keyd::store::KeyStore;
 use prettytable::{cell, row, Table};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::signal::unix::{signal, SignalKind};
 
-pub async fn run(keyd: KeyD) -> Result<()> {
-    let args = App::new("keyD")
-        .subcommand(SubCommand::with_name("agent").about("run ssh agent"))
+pub fn app() -> App<'static, 'static> {
+    App::new("keyD")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .global(true)
+                .help("config file, default $XDG_CONFIG_HOME/keyd/config.toml"),
+        )
+        .arg(
+            Arg::with_name("database")
+                .long("database")
+                .takes_value(true)
+                .global(true)
+                .help("key store database, overrides config and KEYD_DATABASE"),
+        )
+        .arg(
+            Arg::with_name("allow dss")
+                .long("allow-dss")
+                .global(true)
+                .help("allow deprecated DSA (ssh-dss) keys"),
+        )
+        .subcommand(
+            SubCommand::with_name("agent")
+                .about("run ssh agent in background and print environment for `eval`")
+                .arg(
+                    Arg::with_name("sh")
+                        .short("s")
+                        .conflicts_with("csh")
+                        .help("print Bourne shell commands"),
+                )
+                .arg(
+                    Arg::with_name("csh")
+                        .short("c")
+                        .help("print C shell commands"),
+                )
+                .arg(
+                    Arg::with_name("foreground")
+                        .short("D")
+                        .help("run in foreground"),
+                )
+                .arg(
+                    Arg::with_name("kill")
+                        .short("k")
+                        .conflicts_with_all(&["foreground", "command"])
+                        .help("kill agent of SSH_AGENT_PID"),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .multiple(true)
+                        .last(true)
+                        .help("run command with agent, exit when it exits"),
+                )
+                .arg(
+                    Arg::with_name("no confirm")
+                        .long("no-confirm")
+                        .help("only ask approval for keys added with confirm constraint"),
+                )
+                .arg(
+                    Arg::with_name("approver")
+                        .long("approver")
+                        .takes_value(true)
+                        .help("approval backend: notify, terminal, allow, deny or command:<cmd>"),
+                )
+                .arg(
+                    Arg::with_name("approval timeout")
+                        .long("approval-timeout")
+                        .takes_value(true)
+                        .help("seconds to wait for approval"),
+                )
+                .arg(
+                    Arg::with_name("allow uid")
+                        .long("allow-uid")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("also accept connections from processes of this uid"),
+                )
+                .arg(
+                    Arg::with_name("group")
+                        .long("group")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("only serve stored keys of this group id"),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("only serve stored keys with this tag"),
+                )
+                .subcommand(SubCommand::with_name("lock").about("lock running agent"))
+                .subcommand(SubCommand::with_name("unlock").about("unlock running agent"))
+                .subcommand(SubCommand::with_name("status").about("show running agent status")),
+        )
+        .subcommand(
+            SubCommand::with_name("db")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("manage key store database")
+                .subcommand(
+                    SubCommand::with_name("migrate")
+                        .about("upgrade database schema to this version of keyd")
+                        .arg(
+                            Arg::with_name("dry run")
+                                .long("dry-run")
+                                .help("only show pending migrations"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("audit")
+                .about("show audit log of agent operations")
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .help("only events within duration before now, e.g. 7d"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .takes_value(true)
+                        .help("only events before duration before now, e.g. 1d"),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .takes_value(true)
+                        .help("only events of key fingerprint"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("print events as JSON lines"),
+                )
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("delete old audit events")
+                        .arg(
+                            Arg::with_name("older than")
+                                .long("older-than")
+                                .takes_value(true)
+                                .required(true)
+                                .help("age of events to delete, e.g. 180d"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("init").about("set master passphrase encrypting stored keys"),
+        )
+        .subcommand(
+            SubCommand::with_name("unlock").about("load master passphrase into running agent"),
+        )
+        .subcommand(SubCommand::with_name("lock").about("forget master passphrase in running agent"))
         .subcommand(
             SubCommand::with_name("key")
                 .setting(AppSettings::SubcommandRequiredElseHelp)
                 .about("manage key")
                 .subcommand(
-                    SubCommand::with_name("list").arg(
-                        Arg::with_name("group id")
-                            .takes_value(true)
-                            .help("group id"),
-                    ),
+                    SubCommand::with_name("list")
+                        .arg(
+                            Arg::with_name("group id")
+                                .takes_value(true)
+                                .help("group id"),
+                        )
+                        .arg(
+                            Arg::with_name("tag")
+                                .long("tag")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("only keys with this tag, repeat to require several"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("tag")
+                        .about("tag key")
+                        .arg(
+                            Arg::with_name("id")
+                                .help("key id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("tag")
+                                .help("tags to add")
+                                .multiple(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("untag")
+                        .about("remove tag from key")
+                        .arg(
+                            Arg::with_name("id")
+                                .help("key id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("tag")
+                                .help("tags to remove")
+                                .multiple(true)
+                                .required(true),
+                        ),
                 )
                 .subcommand(
                     SubCommand::with_name("add")
@@ -43,6 +253,119 @@ pub async fn run(keyd: KeyD) -> Result<()> {
                             .help("key id to remove")
                             .takes_value(true),
                     ),
+                )
+                .subcommand(
+                    SubCommand::with_name("policy")
+                        .about("show or set approval policy of key")
+                        .arg(
+                            Arg::with_name("id")
+                                .help("key id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("policy")
+                                .help("ask, never, connection, interval:<minutes> or deny")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("clear")
+                                .long("clear")
+                                .conflicts_with("policy")
+                                .help("fall back to group policy"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("expire")
+                        .about("set time after which key is no longer used")
+                        .arg(
+                            Arg::with_name("id")
+                                .help("key id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("after")
+                                .help("duration from now, e.g. 30d, 12h or 2w")
+                                .takes_value(true)
+                                .required_unless("never"),
+                        )
+                        .arg(
+                            Arg::with_name("never")
+                                .long("never")
+                                .conflicts_with("after")
+                                .help("keep key forever"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("stale")
+                        .about("list keys not used for a while")
+                        .arg(
+                            Arg::with_name("older than")
+                                .long("older-than")
+                                .takes_value(true)
+                                .default_value("90d")
+                                .help("duration since last use, or creation if never used"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("cert")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .about("manage OpenSSH certificates of keys")
+                        .subcommand(
+                            SubCommand::with_name("add")
+                                .about("attach certificate to its stored key")
+                                .arg(
+                                    Arg::with_name("path")
+                                        .help("path to certificate file, or read from stdin")
+                                        .takes_value(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("list")
+                                .about("list certificates")
+                                .arg(
+                                    Arg::with_name("key id")
+                                        .help("only certificates of key id")
+                                        .takes_value(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("remove")
+                                .about("remove certificate")
+                                .arg(
+                                    Arg::with_name("id")
+                                        .help("certificate id")
+                                        .takes_value(true)
+                                        .required(true),
+                                ),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tag")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("manage key tags")
+                .subcommand(
+                    SubCommand::with_name("policy")
+                        .about("show or set approval policy of keys with tag")
+                        .arg(
+                            Arg::with_name("tag")
+                                .help("tag")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("policy")
+                                .help("ask, never, connection, interval:<minutes> or deny")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("clear")
+                                .long("clear")
+                                .conflicts_with("policy")
+                                .help("remove tag policy"),
+                        ),
                 ),
         )
         .subcommand(
@@ -84,69 +407,555 @@ pub async fn run(keyd: KeyD) -> Result<()> {
                                 .takes_value(true)
                                 .required(true),
                         ),
+                )
+                .subcommand(
+                    SubCommand::with_name("policy")
+                        .about("show or set approval policy of group")
+                        .arg(
+                            Arg::with_name("id")
+                                .help("group id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("policy")
+                                .help("ask, never, connection, interval:<minutes> or deny")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("clear")
+                                .long("clear")
+                                .conflicts_with("policy")
+                                .help("fall back to agent default"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("host")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .about("restrict keys of group to destination hosts, restricted keys are never used locally")
+                        .subcommand(
+                            SubCommand::with_name("add")
+                                .about("allow host for group")
+                                .arg(
+                                    Arg::with_name("id")
+                                        .help("group id")
+                                        .takes_value(true)
+                                        .required(true),
+                                )
+                                .arg(
+                                    Arg::with_name("host key")
+                                        .help("host key fingerprint, public key file or known_hosts line")
+                                        .takes_value(true)
+                                        .required(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("remove")
+                                .about("disallow host for group")
+                                .arg(
+                                    Arg::with_name("id")
+                                        .help("group id")
+                                        .takes_value(true)
+                                        .required(true),
+                                )
+                                .arg(
+                                    Arg::with_name("host key")
+                                        .help("host key fingerprint, public key file or known_hosts line")
+                                        .takes_value(true)
+                                        .required(true),
+                                ),
+                        )
+                        .subcommand(SubCommand::with_name("list").about("list allowed hosts")),
+                )
+                .subcommand(
+                    SubCommand::with_name("program")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .about("restrict keys of group to client executables")
+                        .subcommand(
+                            SubCommand::with_name("add")
+                                .about("allow executable for group")
+                                .arg(
+                                    Arg::with_name("id")
+                                        .help("group id")
+                                        .takes_value(true)
+                                        .required(true),
+                                )
+                                .arg(
+                                    Arg::with_name("path")
+                                        .help("path to executable")
+                                        .takes_value(true)
+                                        .required(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("remove")
+                                .about("disallow executable for group")
+                                .arg(
+                                    Arg::with_name("id")
+                                        .help("group id")
+                                        .takes_value(true)
+                                        .required(true),
+                                )
+                                .arg(
+                                    Arg::with_name("path")
+                                        .help("path to executable")
+                                        .takes_value(true)
+                                        .required(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("list").about("list allowed executables"),
+                        ),
                 ),
         )
-        .get_matches();
+}
+
+pub async fn run(args: &ArgMatches<'_>, mut keyd: KeyD, config: &Config) -> Result<()> {
+    keyd.set_allow_dss(args.is_present("allow dss"));
+    keyd.set_default_group(config.default_group);
 
     if let Some(args) = args.subcommand_matches("group") {
-        run_group(args, keyd).await?;
+        run_group(args, keyd, config).await?;
         return Ok(());
     }
 
     if let Some(args) = args.subcommand_matches("agent") {
-        run_agent(args, keyd).await?;
+        run_agent(args, keyd, config).await?;
+        return Ok(());
+    }
+
+    match args.subcommand_name() {
+        Some(name @ "init") | Some(name @ "unlock") | Some(name @ "lock") => {
+            run_store(name, keyd, config).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(args) = args.subcommand_matches("tag") {
+        run_tag(args, keyd, config).await?;
+        return Ok(());
+    }
+
+    if let Some(args) = args.subcommand_matches("audit") {
+        run_audit(args, keyd, config).await?;
         return Ok(());
     }
 
     if let Some(args) = args.subcommand_matches("key") {
-        run_key(args, keyd).await?;
+        run_key(args, keyd, config).await?;
         return Ok(());
     }
 
     Ok(())
 }
 
-async fn run_agent(_args: &ArgMatches<'_>, keyd: KeyD) -> Result<()> {
-    let path = {
-        match std::env::var("AGENT_SOCK") {
-            Ok(path) => std::path::PathBuf::from(path),
-            Err(_) => std::env::temp_dir().join(format!("keyd-agent.{}", std::process::id())),
+async fn run_agent(args: &ArgMatches<'_>, keyd: KeyD, config: &Config) -> Result<()> {
+    match args.subcommand() {
+        ("lock", _) => {
+            let passphrase = read_passphrase("Enter lock password: ")?;
+            let again = read_passphrase("Again: ")?;
+            if passphrase != again {
+                error!("passwords do not match");
+                return Ok(());
+            }
+
+            agent_client().await?.lock(passphrase).await?;
+            info!("agent locked");
+            return Ok(());
+        }
+        ("unlock", _) => {
+            let passphrase = read_passphrase("Enter lock password: ")?;
+
+            agent_client().await?.unlock(passphrase).await?;
+            info!("agent unlocked");
+            return Ok(());
+        }
+        ("status", _) => {
+            if let Ok(mut client) = ControlClient::connect(config.control_socket_path()).await {
+                let status: AgentStatus = client.call("agent.status", Value::Null).await?;
+                println!("locked: {}", status.locked);
+                println!("key store locked: {}", status.store_locked);
+                println!("temporary keys:");
+                for fingerprint in status.temporary_keys {
+                    println!("  {}", fingerprint);
+                }
+                println!("pending approvals:");
+                for body in status.pending_approvals {
+                    println!("  {}", body);
+                }
+
+                return Ok(());
+            }
+
+            let locked = agent_client().await?.is_locked().await?;
+            println!("locked: {}", locked);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let shell = if args.is_present("csh") {
+        Shell::Csh
+    } else if args.is_present("sh") {
+        Shell::Sh
+    } else {
+        Shell::detect()
+    };
+
+    if args.is_present("kill") {
+        let pid = std::env::var("SSH_AGENT_PID")
+            .map_err(|_| anyhow::anyhow!("SSH_AGENT_PID not set, cannot kill agent"))?
+            .parse::<i32>()?;
+        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
         }
+
+        print!("{}", shell.unset_env(pid));
+        return Ok(());
+    }
+
+    if config.socket.is_none() {
+        socket::ensure_private_dir(&socket::runtime_dir())?;
+    }
+
+    let command = args
+        .values_of_os("command")
+        .map(|it| it.collect::<Vec<_>>())
+        .unwrap_or_default();
+    // agent running a command has its own socket, besides a daemon on default one
+    let path = match &config.socket {
+        None if !command.is_empty() => socket::process_socket_path(),
+        _ => config.socket_path(),
+    };
+    if !args.is_present("foreground") && command.is_empty() {
+        let pid = spawn_daemon(&path)?;
+        print!("{}", shell.set_env(&path, pid));
+        return Ok(());
+    }
+
+    let timeout = match args.value_of("approval timeout") {
+        Some(timeout) => timeout.parse::<u64>()?,
+        None => config.approval_timeout,
     };
+    let approver = approve::from_spec(
+        args.value_of("approver").unwrap_or(&config.approver),
+        Duration::from_secs(timeout),
+    )?;
 
-    let agent = KeyDAgent::new(keyd).unwrap();
-    {
-        let path = path.clone();
+    let mut agent = KeyDAgent::new(keyd, approver, !args.is_present("no confirm"))?;
+    for uid in args.values_of("allow uid").into_iter().flatten() {
+        agent.allow_uid(uid.parse()?);
+    }
+    for group_id in args.values_of("group").into_iter().flatten() {
+        agent.scope_group(group_id.parse()?);
+    }
+    for tag in args.values_of("tag").into_iter().flatten() {
+        agent.scope_tag(tag);
+    }
+    // bind here so startup fails when another agent owns the socket
+    let listener = socket::bind(&path)?;
+    let control_path = match &config.control_socket {
+        Some(control_path) => control_path.clone(),
+        None => socket::control_socket_path(&path),
+    };
+    let control_path = match socket::bind(&control_path) {
+        Ok(control_listener) => {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                control::serve(agent, control_listener).await.ok();
+            });
+            Some(control_path)
+        }
+        // another agent serves configured control socket, this one runs without
+        Err(Error::AgentRunning(_)) => {
+            warn!(
+                "control socket {} is used by another agent, running without it",
+                control_path.display()
+            );
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(days) = config.audit_retention_days {
+        let keyd = agent.keyd.clone();
         tokio::spawn(async move {
-            agent.run(path).await.ok();
+            let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+            loop {
+                interval.tick().await;
+                let before = unix_now() - days as i64 * 24 * 60 * 60;
+                match keyd.prune_audit(before).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("pruned {} audit events older than {} days", count, days),
+                    Err(e) => error!("failed to prune audit events: {}", e),
+                }
+            }
         });
     }
+    tokio::spawn(async move {
+        agent.serve(listener).await.ok();
+    });
+
+    if let Some((program, args)) = command.split_first() {
+        let mut child = std::process::Command::new(program)
+            .args(args)
+            .env("SSH_AUTH_SOCK", &path)
+            .env("SSH_AGENT_PID", std::process::id().to_string())
+            .spawn()?;
+        let status = tokio::task::spawn_blocking(move || child.wait()).await??;
+
+        std::fs::remove_file(path).ok();
+        if let Some(control_path) = &control_path {
+            std::fs::remove_file(control_path).ok();
+        }
+        std::process::exit(status.code().unwrap_or(1));
+    }
 
-    tokio::signal::ctrl_c().await?;
+    print!("{}", shell.set_env(&path, std::process::id()));
+    std::io::stdout().flush()?;
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        r = tokio::signal::ctrl_c() => r?,
+        _ = terminate.recv() => {}
+    }
 
     std::fs::remove_file(path).ok();
+    if let Some(control_path) = control_path {
+        std::fs::remove_file(control_path).ok();
+    }
 
     Ok(())
 }
 
-async fn run_group(args: &ArgMatches<'_>, keyd: KeyD) -> Result<()> {
+/// shell syntax of environment lines printed for `eval`
+#[derive(Debug, Clone, Copy)]
+enum Shell {
+    Sh,
+    Csh,
+}
+
+impl Shell {
+    /// guess from `SHELL`, same as ssh-agent
+    fn detect() -> Shell {
+        match std::env::var("SHELL") {
+            Ok(shell) if shell.ends_with("csh") => Shell::Csh,
+            _ => Shell::Sh,
+        }
+    }
+
+    fn set_env(self, path: &Path, pid: u32) -> String {
+        match self {
+            Shell::Sh => format!(
+                "SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;\nSSH_AGENT_PID={}; export SSH_AGENT_PID;\necho Agent pid {};\n",
+                path.display(),
+                pid,
+                pid
+            ),
+            Shell::Csh => format!(
+                "setenv SSH_AUTH_SOCK {};\nsetenv SSH_AGENT_PID {};\necho Agent pid {};\n",
+                path.display(),
+                pid,
+                pid
+            ),
+        }
+    }
+
+    fn unset_env(self, pid: i32) -> String {
+        match self {
+            Shell::Sh => format!(
+                "unset SSH_AUTH_SOCK;\nunset SSH_AGENT_PID;\necho Agent pid {} killed;\n",
+                pid
+            ),
+            Shell::Csh => format!(
+                "unsetenv SSH_AUTH_SOCK;\nunsetenv SSH_AGENT_PID;\necho Agent pid {} killed;\n",
+                pid
+            ),
+        }
+    }
+}
+
+/// re-run this command as foreground agent in a new session, return its pid once it listens
+fn spawn_daemon(path: &Path) -> Result<u32> {
+    use std::os::unix::process::CommandExt;
+
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("agent already running on {}", path.display());
+    }
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("-D")
+        .env("AGENT_SOCK", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Ok(child.id());
+        }
+
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("agent exited on start: {}", status);
+        }
+
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            anyhow::bail!("agent not listening after 5s");
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// connect to agent from `SSH_AUTH_SOCK`
+async fn agent_client() -> Result<AgentClient> {
+    let path = std::env::var("SSH_AUTH_SOCK")
+        .map_err(|_| anyhow::anyhow!("SSH_AUTH_SOCK not set, is agent running?"))?;
+
+    Ok(AgentClient::connect(path).await?)
+}
+
+/// parse duration like `90d`, `12h`, `30m`, `2w` or plain seconds
+fn parse_duration(s: &str) -> Result<i64> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("unknown unit in duration {}, expect s, m, h, d or w", s),
+    };
+
+    Ok(number.parse::<i64>()? * unit)
+}
+
+/// unix time relative to `now`, like `3d ago` or `in 2h`
+fn format_time(time: Option<i64>, now: i64) -> String {
+    let time = match time {
+        Some(time) => time,
+        None => return "-".into(),
+    };
+
+    let delta = (now - time).abs();
+    let amount = match delta {
+        d if d < 60 => format!("{}s", d),
+        d if d < 60 * 60 => format!("{}m", d / 60),
+        d if d < 24 * 60 * 60 => format!("{}h", d / (60 * 60)),
+        d => format!("{}d", d / (24 * 60 * 60)),
+    };
+
+    if time <= now {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+/// unix time as `YYYY-MM-DD HH:MM:SS` in UTC
+fn format_utc(time: i64) -> String {
+    let (days, seconds) = (time.div_euclid(86400), time.rem_euclid(86400));
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// read a line from terminal without echo
+fn read_passphrase(prompt: &str) -> Result<String> {
+    use std::os::unix::io::AsRawFd;
+
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+
+    let stdin = std::io::stdin();
+    let fd = stdin.as_raw_fd();
+
+    let mut term: libc::termios = unsafe { std::mem::zeroed() };
+    let is_tty = unsafe { libc::tcgetattr(fd, &mut term) } == 0;
+    if is_tty {
+        let mut no_echo = term;
+        no_echo.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &no_echo) };
+    }
+
+    let mut line = String::new();
+    let r = stdin.read_line(&mut line);
+
+    if is_tty {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) };
+        eprintln!();
+    }
+    r?;
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+async fn run_group(args: &ArgMatches<'_>, keyd: KeyD, config: &Config) -> Result<()> {
+    let mut backend = Backend::new(keyd, config).await;
+    match args.subcommand() {
+        ("host", Some(args)) => return run_group_host(args, &mut backend).await,
+        ("program", Some(args)) => return run_group_program(args, &mut backend).await,
+        _ => {}
+    }
+
     match args.subcommand() {
         ("add", Some(args)) => {
             let name = args.value_of("name").unwrap();
-            let id = keyd.create_group(name).await?;
+            let id: i64 = backend.call("group.add", json!({ "name": name })).await?;
 
             info!("group added, id: {}", id);
         }
         ("list", _) => {
-            let groups = keyd.list_groups().await?;
-            dbg!(groups);
+            let groups: Vec<KeyGroup> = backend.call("group.list", Value::Null).await?;
+
+            let mut table = Table::new();
+            table.set_titles(row!["ID", "Name"]);
+            for group in groups {
+                table.add_row(row![group.id, group.name]);
+            }
+
+            table.printstd();
         }
         ("rename", Some(args)) => {
             let id = args.value_of("id").unwrap();
             let new_name = args.value_of("new name").unwrap();
 
             let id = id.parse::<i64>()?;
-            keyd.rename_group(id, new_name).await?;
+            backend
+                .call::<()>("group.rename", json!({ "id": id, "name": new_name }))
+                .await?;
 
             info!("group {} rename to {}", id, new_name);
         }
@@ -161,17 +970,199 @@ async fn run_group(args: &ArgMatches<'_>, keyd: KeyD) -> Result<()> {
                 return Ok(());
             }
 
-            keyd.delete_group(id).await?;
+            backend
+                .call::<()>("group.remove", json!({ "id": id }))
+                .await?;
 
             info!("remove group {}", id);
         }
+        ("policy", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            if args.is_present("clear") {
+                backend
+                    .call::<()>("group.policy.set", json!({ "id": id, "policy": null }))
+                    .await?;
+                info!("group {} policy cleared", id);
+            } else if let Some(policy) = args.value_of("policy") {
+                let policy = policy.parse::<Policy>()?;
+                backend
+                    .call::<()>(
+                        "group.policy.set",
+                        json!({ "id": id, "policy": policy.to_string() }),
+                    )
+                    .await?;
+                info!("group {} policy set to {}", id, policy);
+            } else {
+                let policy: Option<String> = backend
+                    .call("group.policy.get", json!({ "id": id }))
+                    .await?;
+                println!("{}", policy.as_deref().unwrap_or("default"));
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn run_tag(args: &ArgMatches<'_>, keyd: KeyD, config: &Config) -> Result<()> {
+    let mut backend = Backend::new(keyd, config).await;
+    match args.subcommand() {
+        ("policy", Some(args)) => {
+            let tag = args.value_of("tag").unwrap();
+            if args.is_present("clear") {
+                backend
+                    .call::<()>("tag.policy.set", json!({ "tag": tag, "policy": null }))
+                    .await?;
+                info!("tag {} policy cleared", tag);
+            } else if let Some(policy) = args.value_of("policy") {
+                let policy = policy.parse::<Policy>()?;
+                backend
+                    .call::<()>(
+                        "tag.policy.set",
+                        json!({ "tag": tag, "policy": policy.to_string() }),
+                    )
+                    .await?;
+                info!("tag {} policy set to {}", tag, policy);
+            } else {
+                let policy: Option<String> = backend
+                    .call("tag.policy.get", json!({ "tag": tag }))
+                    .await?;
+                println!("{}", policy.as_deref().unwrap_or("default"));
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn run_group_host(args: &ArgMatches<'_>, backend: &mut Backend) -> Result<()> {
+    match args.subcommand() {
+        ("add", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            let fingerprint = host_fingerprint(args.value_of("host key").unwrap())?;
+            backend
+                .call::<()>(
+                    "group.host.add",
+                    json!({ "id": id, "fingerprint": fingerprint }),
+                )
+                .await?;
+
+            info!("group {} allowed for host {}", id, fingerprint);
+        }
+        ("remove", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            let fingerprint = host_fingerprint(args.value_of("host key").unwrap())?;
+            backend
+                .call::<()>(
+                    "group.host.remove",
+                    json!({ "id": id, "fingerprint": fingerprint }),
+                )
+                .await?;
+
+            info!("group {} disallowed for host {}", id, fingerprint);
+        }
+        ("list", _) => {
+            let mut table = Table::new();
+            table.set_titles(row!["GroupId", "HostKey"]);
+
+            let group_hosts: HashMap<i64, Vec<String>> =
+                backend.call("group.host.list", json!(null)).await?;
+            for (group_id, hosts) in group_hosts {
+                for host in hosts {
+                    table.add_row(row![group_id, host]);
+                }
+            }
+
+            table.printstd();
+        }
         _ => unreachable!(),
     }
 
     Ok(())
 }
 
-async fn run_key(args: &ArgMatches<'_>, mut keyd: KeyD) -> Result<()> {
+async fn run_group_program(args: &ArgMatches<'_>, backend: &mut Backend) -> Result<()> {
+    match args.subcommand() {
+        ("add", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            // match against resolved `/proc/<pid>/exe` of clients
+            let exe = std::fs::canonicalize(args.value_of("path").unwrap())?;
+            let exe = exe.to_string_lossy();
+            backend
+                .call::<()>("group.program.add", json!({ "id": id, "exe": exe }))
+                .await?;
+
+            info!("group {} allowed for program {}", id, exe);
+        }
+        ("remove", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            let path = args.value_of("path").unwrap();
+            let exe = std::fs::canonicalize(path)
+                .map(|it| it.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| path.to_owned());
+            backend
+                .call::<()>("group.program.remove", json!({ "id": id, "exe": exe }))
+                .await?;
+
+            info!("group {} disallowed for program {}", id, exe);
+        }
+        ("list", _) => {
+            let mut table = Table::new();
+            table.set_titles(row!["GroupId", "Program"]);
+
+            let group_programs: HashMap<i64, Vec<String>> =
+                backend.call("group.program.list", json!(null)).await?;
+            for (group_id, programs) in group_programs {
+                for program in programs {
+                    table.add_row(row![group_id, program]);
+                }
+            }
+
+            table.printstd();
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// resolve host key fingerprint from fingerprint, public key file or known_hosts line
+fn host_fingerprint(spec: &str) -> Result<String> {
+    if spec.starts_with("SHA256:") {
+        return Ok(spec.to_owned());
+    }
+
+    let content = match std::fs::read_to_string(spec) {
+        Ok(content) => content,
+        Err(_) => spec.to_owned(),
+    };
+
+    let mut parts = content.split_whitespace();
+    let encoded = loop {
+        match parts.next() {
+            Some(part) if part.starts_with("ssh-") || part.starts_with("ecdsa-") => {
+                break parts.next();
+            }
+            Some(_) => continue,
+            None => break None,
+        }
+    }
+    .ok_or_else(|| anyhow::anyhow!("no public key found in {}", spec))?;
+
+    let blob = openssl::base64::decode_block(encoded)?;
+    let key = libsshkey::key::parse_public_blob(bytes::Bytes::from(blob))?;
+
+    Ok(key.fingerprint(HashType::SHA256)?)
+}
+
+async fn run_key(args: &ArgMatches<'_>, keyd: KeyD, config: &Config) -> Result<()> {
+    let mut backend = Backend::new(keyd, config).await;
+    if let ("cert", Some(args)) = args.subcommand() {
+        return run_cert(args, &mut backend).await;
+    }
+
     match args.subcommand() {
         ("add", Some(args)) => {
             let group_id = args
@@ -193,35 +1184,63 @@ async fn run_key(args: &ArgMatches<'_>, mut keyd: KeyD) -> Result<()> {
                 }
             };
 
-            let key = parse_private_pem(content.as_bytes(), None::<&str>)?;
-
-            let item = keyd.add(group_id, name, key).await?;
-            info!("key {} added", item.item.fingerprint);
+            backend.unlock_direct().await?;
+            let item: KeyInfo = backend
+                .call(
+                    "key.add",
+                    json!({ "group_id": group_id, "name": name, "key": content }),
+                )
+                .await?;
+            info!("key {} added", item.fingerprint);
         }
         ("list", Some(args)) => {
             let group_id = args
                 .value_of("group id")
                 .and_then(|it| it.parse::<i64>().ok());
+            let tags = args
+                .values_of("tag")
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            let keys: Vec<KeyInfo> = backend
+                .call("key.list", json!({ "group_id": group_id, "tags": tags }))
+                .await?;
+            let now = unix_now();
+
             match group_id {
-                Some(group_id) => {
-                    let keys = keyd.list_group_keys(group_id).await?;
+                Some(_) => {
                     let mut table = Table::new();
-                    table.set_titles(row!["ID", "Name", "KeyType", "Fingerprint", "PublicKey",]);
+                    table.set_titles(row![
+                        "ID",
+                        "Name",
+                        "KeyType",
+                        "Fingerprint",
+                        "PublicKey",
+                        "Created",
+                        "LastUsed",
+                        "Uses",
+                        "Expires",
+                        "Tags"
+                    ]);
 
                     for k in keys {
                         table.add_row(row![
-                            k.item.id,
-                            k.item.name,
-                            k.item.key_type,
-                            k.item.fingerprint,
-                            k.item.public_key[0..32],
+                            k.id,
+                            k.name,
+                            k.key_type,
+                            k.fingerprint,
+                            k.public_key[0..32],
+                            format_time(Some(k.created_at), now),
+                            format_time(k.last_used_at, now),
+                            k.sign_count,
+                            format_time(k.expires_at, now),
+                            k.tags.join(","),
                         ]);
                     }
 
                     table.printstd();
                 }
                 None => {
-                    let keys = keyd.get_all().await?;
                     let mut table = Table::new();
                     table.set_titles(row![
                         "ID",
@@ -229,17 +1248,27 @@ async fn run_key(args: &ArgMatches<'_>, mut keyd: KeyD) -> Result<()> {
                         "KeyType",
                         "Fingerprint",
                         "PublicKey",
-                        "GroupId"
+                        "GroupId",
+                        "Created",
+                        "LastUsed",
+                        "Uses",
+                        "Expires",
+                        "Tags"
                     ]);
 
                     for k in keys {
                         table.add_row(row![
-                            k.item.id,
-                            k.item.name,
-                            k.item.key_type,
-                            k.item.fingerprint,
-                            k.item.public_key[0..32],
-                            k.item.group_id.unwrap(),
+                            k.id,
+                            k.name,
+                            k.key_type,
+                            k.fingerprint,
+                            k.public_key[0..32],
+                            k.group_id.unwrap(),
+                            format_time(Some(k.created_at), now),
+                            format_time(k.last_used_at, now),
+                            k.sign_count,
+                            format_time(k.expires_at, now),
+                            k.tags.join(","),
                         ]);
                     }
 
@@ -247,17 +1276,365 @@ async fn run_key(args: &ArgMatches<'_>, mut keyd: KeyD) -> Result<()> {
                 }
             }
         }
+        ("expire", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            let expires_at = match args.value_of("after") {
+                Some(after) => Some(unix_now() + parse_duration(after)?),
+                None => None,
+            };
+            backend
+                .call::<()>("key.expire", json!({ "id": id, "expires_at": expires_at }))
+                .await?;
+
+            match expires_at {
+                Some(_) => info!("key {} expires in {}", id, args.value_of("after").unwrap()),
+                None => info!("key {} never expires", id),
+            }
+        }
+        (command @ "tag", Some(args)) | (command @ "untag", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            for tag in args.values_of("tag").into_iter().flatten() {
+                backend
+                    .call::<()>(&format!("key.{}", command), json!({ "id": id, "tag": tag }))
+                    .await?;
+            }
+
+            info!("key {} {}ged", id, command);
+        }
+        ("stale", Some(args)) => {
+            let older_than = parse_duration(args.value_of("older than").unwrap())?;
+            let keys: Vec<KeyInfo> = backend.call("key.list", Value::Null).await?;
+            let now = unix_now();
+
+            let mut table = Table::new();
+            table.set_titles(row![
+                "ID",
+                "Name",
+                "Fingerprint",
+                "GroupId",
+                "Created",
+                "LastUsed",
+                "Uses"
+            ]);
+
+            for k in keys {
+                if k.last_used_at.unwrap_or(k.created_at) > now - older_than {
+                    continue;
+                }
+
+                table.add_row(row![
+                    k.id,
+                    k.name,
+                    k.fingerprint,
+                    k.group_id.unwrap(),
+                    format_time(Some(k.created_at), now),
+                    format_time(k.last_used_at, now),
+                    k.sign_count,
+                ]);
+            }
+
+            table.printstd();
+        }
         ("remove", Some(args)) => {
             let id = args
                 .value_of("id")
                 .and_then(|it| it.parse::<i64>().ok())
                 .unwrap();
-            let _ = keyd.remove(id).await?;
+            backend
+                .call::<()>("key.remove", json!({ "id": id }))
+                .await?;
 
             info!("key {} removed", id);
         }
+        ("policy", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            if args.is_present("clear") {
+                backend
+                    .call::<()>("key.policy.set", json!({ "id": id, "policy": null }))
+                    .await?;
+                info!("key {} policy cleared", id);
+            } else if let Some(policy) = args.value_of("policy") {
+                let policy = policy.parse::<Policy>()?;
+                backend
+                    .call::<()>(
+                        "key.policy.set",
+                        json!({ "id": id, "policy": policy.to_string() }),
+                    )
+                    .await?;
+                info!("key {} policy set to {}", id, policy);
+            } else {
+                let info: PolicyInfo = backend.call("key.policy.get", json!({ "id": id })).await?;
+                match (info.policy, info.effective) {
+                    (Some(policy), _) => println!("{}", policy),
+                    (None, Some(policy)) => println!("{} (tag or group)", policy),
+                    (None, None) => println!("default"),
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// run `db` subcommand on store not yet migrated
+pub async fn run_db(args: &ArgMatches<'_>, store: &KeyStore) -> Result<()> {
+    match args.subcommand() {
+        ("migrate", Some(args)) => {
+            let version = store.version().await?;
+            let pending = store.pending_migrations().await?;
+            if pending.is_empty() {
+                info!("database schema is up to date at version {}", version);
+                return Ok(());
+            }
+
+            for migration in &pending {
+                println!("{:>4}  {}", migration.version, migration.description);
+            }
+            if args.is_present("dry run") {
+                store.check_migrations().await?;
+                return Ok(());
+            }
+
+            store.init().await?;
+            info!(
+                "database schema migrated from version {} to {}",
+                version,
+                store.version().await?
+            );
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn run_audit(args: &ArgMatches<'_>, keyd: KeyD, config: &Config) -> Result<()> {
+    let mut backend = Backend::new(keyd, config).await;
+    let now = unix_now();
+
+    if let ("prune", Some(args)) = args.subcommand() {
+        let before = now - parse_duration(args.value_of("older than").unwrap())?;
+        let count: u64 = backend
+            .call("audit.prune", json!({ "before": before }))
+            .await?;
+        info!("pruned {} audit events", count);
+
+        return Ok(());
+    }
+
+    let since = args
+        .value_of("since")
+        .map(|it| parse_duration(it).map(|it| now - it))
+        .transpose()?;
+    let until = args
+        .value_of("until")
+        .map(|it| parse_duration(it).map(|it| now - it))
+        .transpose()?;
+    let events: Vec<AuditEvent> = backend
+        .call(
+            "audit.list",
+            json!({ "since": since, "until": until, "fingerprint": args.value_of("key") }),
+        )
+        .await?;
+
+    if args.is_present("json") {
+        for event in events {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_titles(row![
+        "Time",
+        "Operation",
+        "Fingerprint",
+        "Outcome",
+        "Peer",
+        "Context"
+    ]);
+
+    for e in events {
+        let peer = match (e.peer_exe, e.peer_pid) {
+            (Some(exe), Some(pid)) => format!("{} ({})", exe, pid),
+            (Some(exe), None) => exe,
+            (None, Some(pid)) => format!("pid {}", pid),
+            (None, None) => "-".into(),
+        };
+
+        table.add_row(row![
+            format_utc(e.time),
+            e.operation,
+            e.fingerprint.as_deref().unwrap_or("-"),
+            e.outcome.as_deref().unwrap_or("-"),
+            peer,
+            e.context.as_deref().unwrap_or("-"),
+        ]);
+    }
+
+    table.printstd();
+
+    Ok(())
+}
+
+/// set, load or forget master passphrase of key store
+async fn run_store(command: &str, keyd: KeyD, config: &Config) -> Result<()> {
+    let mut backend = Backend::new(keyd, config).await;
+    match command {
+        "init" => {
+            let passphrase = read_passphrase("Enter new master passphrase: ")?;
+            let again = read_passphrase("Again: ")?;
+            if passphrase != again {
+                error!("passphrases do not match");
+                return Ok(());
+            }
+            if passphrase.is_empty() {
+                error!("master passphrase must not be empty");
+                return Ok(());
+            }
+
+            backend
+                .call::<()>("store.init", json!({ "passphrase": passphrase }))
+                .await?;
+            info!("stored keys encrypted");
+        }
+        "unlock" | "lock" => {
+            if let Backend::Direct(_) = backend {
+                anyhow::bail!(
+                    "no running agent on {}",
+                    config.control_socket_path().display()
+                );
+            }
 
+            if command == "unlock" {
+                let passphrase = read_passphrase("Enter master passphrase: ")?;
+                backend
+                    .call::<()>("store.unlock", json!({ "passphrase": passphrase }))
+                    .await?;
+                info!("key store unlocked");
+            } else {
+                backend.call::<()>("store.lock", Value::Null).await?;
+                info!("key store locked");
+            }
+        }
         _ => unreachable!(),
     }
+
     Ok(())
 }
+
+/// running daemon over control socket, or key store directly when no daemon runs
+enum Backend {
+    Daemon(ControlClient),
+    Direct(KeyD),
+}
+
+impl Backend {
+    async fn new(keyd: KeyD, config: &Config) -> Backend {
+        match ControlClient::connect(config.control_socket_path()).await {
+            Ok(client) => Backend::Daemon(client),
+            Err(_) => Backend::Direct(keyd),
+        }
+    }
+
+    /// ask master passphrase when using encrypted key store directly
+    async fn unlock_direct(&mut self) -> Result<()> {
+        if let Backend::Direct(keyd) = self {
+            if keyd.is_store_locked().await? {
+                let passphrase = read_passphrase("Enter master passphrase: ")?;
+                keyd.unlock_master(&passphrase).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn call<T: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T> {
+        match self {
+            Backend::Daemon(client) => Ok(client.call(method, params).await?),
+            Backend::Direct(keyd) => {
+                let result = call_keyd(keyd, method, params)
+                    .await
+                    .map_err(|e| anyhow::anyhow!(e.message))?;
+
+                Ok(serde_json::from_value(result)?)
+            }
+        }
+    }
+}
+
+async fn run_cert(args: &ArgMatches<'_>, backend: &mut Backend) -> Result<()> {
+    match args.subcommand() {
+        ("add", Some(args)) => {
+            let content = match args.value_of("path") {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => {
+                    let mut content = String::new();
+                    let _r = std::io::stdin().lock().read_to_string(&mut content)?;
+
+                    content
+                }
+            };
+
+            let cert: CertInfo = backend
+                .call("key.cert.add", json!({ "certificate": content.trim() }))
+                .await?;
+
+            info!("certificate {} attached to key {}", cert.id, cert.key_id);
+        }
+        ("list", Some(args)) => {
+            let key_id = args
+                .value_of("key id")
+                .and_then(|it| it.parse::<i64>().ok());
+            let certs: Vec<CertInfo> = backend
+                .call("key.cert.list", json!({ "key_id": key_id }))
+                .await?;
+            let now = unix_now();
+
+            let mut table = Table::new();
+            table.set_titles(row!["ID", "KeyId", "Type", "Identity", "Expired"]);
+
+            for c in certs {
+                table.add_row(row![
+                    c.id,
+                    c.key_id,
+                    c.cert_type,
+                    c.identity,
+                    c.valid_before <= now,
+                ]);
+            }
+
+            table.printstd();
+        }
+        ("remove", Some(args)) => {
+            let id = args.value_of("id").unwrap().parse::<i64>()?;
+            backend
+                .call::<()>("key.cert.remove", json!({ "id": id }))
+                .await?;
+
+            info!("certificate {} removed", id);
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::parse_duration;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("45").unwrap(), 45);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("12h").unwrap(), 12 * 60 * 60);
+        assert_eq!(parse_duration("90d").unwrap(), 90 * 24 * 60 * 60);
+        assert_eq!(parse_duration("2w").unwrap(), 14 * 24 * 60 * 60);
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("1h30m").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use libsshkey::SSHBuffer;
+use tokio::net::UnixStream;
+
+use crate::error::{Error, Result};
+use crate::framed::Framed;
+use crate::parse::{build_request, ClientMessageType, ServerResponseType, EXTENSION_STATUS};
+
+/// minimal ssh agent client, used by cli to control a running agent
+#[derive(Debug)]
+pub struct AgentClient {
+    stream: Framed<UnixStream>,
+}
+
+impl AgentClient {
+    pub async fn connect(path: impl AsRef<Path>) -> Result<AgentClient> {
+        let stream = UnixStream::connect(path).await?;
+
+        Ok(AgentClient {
+            stream: Framed::new(stream),
+        })
+    }
+
+    /// send request and return reply body after the message type
+    async fn request(&mut self, ty: ClientMessageType, body: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        self.stream.write_packet(build_request(ty, body)).await?;
+
+        let reply = self
+            .stream
+            .read_packet()
+            .await?
+            .ok_or_else(|| Error::IOError(std::io::ErrorKind::UnexpectedEof.into()))?;
+
+        match reply.get(4).copied() {
+            Some(ty) if ty == ServerResponseType::SshAgentSuccess as u8 => Ok(reply[5..].to_vec()),
+            _ => Err(Error::AgentFailure),
+        }
+    }
+
+    /// lock agent with passphrase, as `ssh-add -x`
+    pub async fn lock(&mut self, passphrase: impl AsRef<[u8]>) -> Result<()> {
+        let mut buf = SSHBuffer::empty()?;
+        buf.put_string(passphrase.as_ref())?;
+
+        self.request(ClientMessageType::SshAgentLock, &*buf).await?;
+
+        Ok(())
+    }
+
+    /// unlock agent with passphrase, as `ssh-add -X`
+    pub async fn unlock(&mut self, passphrase: impl AsRef<[u8]>) -> Result<()> {
+        let mut buf = SSHBuffer::empty()?;
+        buf.put_string(passphrase.as_ref())?;
+
+        self.request(ClientMessageType::SshAgentUnlock, &*buf).await?;
+
+        Ok(())
+    }
+
+    /// query whether agent is locked
+    pub async fn is_locked(&mut self) -> Result<bool> {
+        let mut buf = SSHBuffer::empty()?;
+        buf.put_string(EXTENSION_STATUS)?;
+
+        let reply = self
+            .request(ClientMessageType::SshAgentExtension, &*buf)
+            .await?;
+
+        Ok(reply.first().copied().unwrap_or_default() != 0)
+    }
+}
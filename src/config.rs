@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::socket;
+
+/// keyd settings from `config.toml`, environment overrides applied by `load`.
+///
+/// every field is optional in file, e.g.
+///
+/// ```toml
+/// database = "/home/alice/.local/share/keyd/key.db"
+/// socket = "/run/user/1000/keyd/agent.sock"
+/// default_group = 1
+/// approver = "notify"
+/// approval_timeout = 5
+/// log = "info"
+/// audit_retention_days = 365
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// sqlite key store, `KEYD_DATABASE`
+    pub database: PathBuf,
+
+    /// agent socket, `AGENT_SOCK`, private runtime directory when unset
+    pub socket: Option<PathBuf>,
+
+    /// daemon control socket, `KEYD_CONTROL_SOCK`, `<socket>.ctl` when unset
+    pub control_socket: Option<PathBuf>,
+
+    /// group of keys added without one, `KEYD_DEFAULT_GROUP`
+    pub default_group: i64,
+
+    /// approval backend spec, `KEYD_APPROVER`
+    pub approver: String,
+
+    /// seconds to wait for approval, `KEYD_APPROVAL_TIMEOUT`
+    pub approval_timeout: u64,
+
+    /// log level of keyd, `KEYD_LOG`, `RUST_LOG` directives still apply
+    pub log: String,
+
+    /// days agent keeps audit events, forever when unset, `KEYD_AUDIT_RETENTION_DAYS`
+    pub audit_retention_days: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database: xdg_dir("XDG_DATA_HOME", ".local/share").join("key.db"),
+            socket: None,
+            control_socket: None,
+            default_group: 1,
+            approver: "notify".into(),
+            approval_timeout: 5,
+            log: "info".into(),
+            audit_retention_days: None,
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/keyd/config.toml`
+    pub fn default_path() -> PathBuf {
+        xdg_dir("XDG_CONFIG_HOME", ".config").join("config.toml")
+    }
+
+    /// load config from `path`, or from default path if it exists, then apply environment.
+    ///
+    /// an explicit `path` must exist.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let content = match path {
+            Some(path) => Some(
+                std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("failed read config {}: {}", path.display(), e))?,
+            ),
+            None => std::fs::read_to_string(Config::default_path()).ok(),
+        };
+
+        let mut config = match content {
+            Some(content) => toml::from_str(&content)?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        let var = |name: &str| std::env::var_os(name).filter(|it| !it.is_empty());
+
+        if let Some(database) = var("KEYD_DATABASE") {
+            self.database = database.into();
+        }
+        if let Some(path) = var("AGENT_SOCK") {
+            self.socket = Some(path.into());
+        }
+        if let Some(path) = var("KEYD_CONTROL_SOCK") {
+            self.control_socket = Some(path.into());
+        }
+        if let Ok(group) = std::env::var("KEYD_DEFAULT_GROUP") {
+            self.default_group = group.parse()?;
+        }
+        if let Ok(approver) = std::env::var("KEYD_APPROVER") {
+            self.approver = approver;
+        }
+        if let Ok(timeout) = std::env::var("KEYD_APPROVAL_TIMEOUT") {
+            self.approval_timeout = timeout.parse()?;
+        }
+        if let Ok(log) = std::env::var("KEYD_LOG") {
+            self.log = log;
+        }
+        if let Ok(days) = std::env::var("KEYD_AUDIT_RETENTION_DAYS") {
+            self.audit_retention_days = Some(days.parse()?);
+        }
+
+        Ok(())
+    }
+
+    /// sqlite url of `database`, creating its directory private when missing
+    pub fn database_url(&self) -> anyhow::Result<String> {
+        if let Some(dir) = self.database.parent() {
+            if !dir.as_os_str().is_empty() && !dir.exists() {
+                socket::ensure_private_dir(dir)?;
+            }
+        }
+
+        Ok(format!("sqlite://{}", self.database.display()))
+    }
+
+    /// agent socket path, in private runtime directory when not configured
+    pub fn socket_path(&self) -> PathBuf {
+        match &self.socket {
+            Some(path) => path.clone(),
+            None => socket::default_socket_path(),
+        }
+    }
+
+    /// control socket path, next to agent socket when not configured
+    pub fn control_socket_path(&self) -> PathBuf {
+        match &self.control_socket {
+            Some(path) => path.clone(),
+            None => socket::control_socket_path(&self.socket_path()),
+        }
+    }
+}
+
+/// `keyd` directory in XDG base directory `var`, or `$HOME/<fallback>`
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    let base = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+            PathBuf::from(home).join(fallback)
+        }
+    };
+
+    base.join("keyd")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::config::Config;
+
+    #[test]
+    fn partial_config() {
+        let config: Config = toml::from_str(
+            r#"
+            database = "/var/lib/keyd/key.db"
+            approver = "terminal"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.database, PathBuf::from("/var/lib/keyd/key.db"));
+        assert_eq!(config.approver, "terminal");
+        assert_eq!(config.approval_timeout, 5);
+        assert_eq!(config.default_group, 1);
+
+        assert!(toml::from_str::<Config>("databse = \"typo\"").is_err());
+    }
+}
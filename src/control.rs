@@ -0,0 +1,711 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::agent::KeyDAgent;
+use crate::cert::parse_cert_line;
+use crate::error::{Error, Result};
+use crate::keyd::KeyD;
+use crate::parse::{parse_private_key_file, Request, ServerResponseType};
+use crate::peer::Peer;
+use crate::store::models::{Key, KeyCert, KeyItem, Policy};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// JSON-RPC 2.0 error object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        RpcError {
+            code: SERVER_ERROR,
+            message: e.to_string(),
+        }
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcResponse {
+    jsonrpc: String,
+    id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: RpcResult) -> RpcResponse {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        RpcResponse {
+            jsonrpc: "2.0".into(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// stored key as exposed over control socket, without private key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub id: i64,
+    pub name: String,
+    pub fingerprint: String,
+    pub key_type: String,
+    pub public_key: String,
+    pub group_id: Option<i64>,
+
+    /// unix times, `last_used_at` is `None` for keys never used
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub sign_count: i64,
+    pub expires_at: Option<i64>,
+
+    /// sorted tags, filled by `key.list`
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl From<&KeyItem> for KeyInfo {
+    fn from(item: &KeyItem) -> Self {
+        KeyInfo {
+            id: item.id,
+            name: item.name.clone(),
+            fingerprint: item.fingerprint.clone(),
+            key_type: item.key_type.to_string(),
+            public_key: item.public_key.clone(),
+            group_id: item.group_id,
+            created_at: item.created_at,
+            last_used_at: item.last_used_at,
+            sign_count: item.sign_count,
+            expires_at: item.expires_at,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl From<&Key> for KeyInfo {
+    fn from(key: &Key) -> Self {
+        KeyInfo::from(&key.item)
+    }
+}
+
+/// certificate attached to a stored key, without certificate blob
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertInfo {
+    pub id: i64,
+    pub key_id: i64,
+    pub cert_type: String,
+    pub identity: String,
+
+    /// unix time
+    pub valid_before: i64,
+}
+
+impl From<&KeyCert> for CertInfo {
+    fn from(cert: &KeyCert) -> Self {
+        CertInfo {
+            id: cert.id,
+            key_id: cert.key_id,
+            cert_type: cert.cert_type.clone(),
+            identity: cert.identity.clone(),
+            valid_before: cert.valid_before,
+        }
+    }
+}
+
+/// policy set on a key and the one in effect, which may come from its group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyInfo {
+    pub policy: Option<String>,
+    pub effective: Option<String>,
+}
+
+/// runtime state of a running agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub locked: bool,
+
+    /// private keys are encrypted and master passphrase is not loaded
+    pub store_locked: bool,
+
+    /// fingerprints of keys added with lifetime, kept in memory only
+    pub temporary_keys: Vec<String>,
+
+    /// descriptions of sign requests waiting for user approval
+    pub pending_approvals: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdParams {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListKeys {
+    group_id: Option<i64>,
+
+    /// only keys with all these tags
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagKey {
+    id: i64,
+    tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagParams {
+    tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetTagPolicy {
+    tag: String,
+
+    /// `None` to clear
+    policy: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddKey {
+    group_id: Option<i64>,
+    name: Option<String>,
+
+    /// private key file content
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddGroup {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameGroup {
+    id: i64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupHost {
+    id: i64,
+
+    /// SHA256 fingerprint of host key
+    fingerprint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupProgram {
+    id: i64,
+
+    /// resolved executable path
+    exe: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListCerts {
+    key_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddCert {
+    /// certificate line as in `*-cert.pub` files
+    certificate: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAudit {
+    since: Option<i64>,
+    until: Option<i64>,
+    fingerprint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PruneAudit {
+    /// unix time, older events are deleted
+    before: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetExpiry {
+    id: i64,
+
+    /// unix time, `None` to keep forever
+    expires_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetPolicy {
+    id: i64,
+
+    /// `None` to clear
+    policy: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Passphrase {
+    passphrase: String,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+
+    serde_json::from_value(params).map_err(|e| RpcError {
+        code: INVALID_PARAMS,
+        message: e.to_string(),
+    })
+}
+
+fn to_value<T: Serialize>(value: T) -> RpcResult {
+    serde_json::to_value(value).map_err(|e| RpcError::from(Error::from(e)))
+}
+
+fn parse_policy(policy: Option<String>) -> std::result::Result<Option<Policy>, RpcError> {
+    policy
+        .map(|it| it.parse::<Policy>())
+        .transpose()
+        .map_err(|e| RpcError {
+            code: INVALID_PARAMS,
+            message: e.to_string(),
+        })
+}
+
+/// serve key store `method`, used by daemon and by cli when no daemon runs
+pub async fn call_keyd(keyd: &mut KeyD, method: &str, params_: Value) -> RpcResult {
+    match method {
+        "key.list" => {
+            let ListKeys { group_id, tags } = params(params_)?;
+            let items = keyd.list_items(group_id).await?;
+            let mut key_tags = keyd.key_tags().await?;
+
+            let mut keys = Vec::new();
+            for item in &items {
+                let mut info = KeyInfo::from(item);
+                info.tags = key_tags.remove(&item.id).into_iter().flatten().collect();
+                info.tags.sort();
+
+                if tags.iter().all(|it| info.tags.contains(it)) {
+                    keys.push(info);
+                }
+            }
+
+            to_value(keys)
+        }
+        "key.add" => {
+            let AddKey {
+                group_id,
+                name,
+                key,
+            } = params(params_)?;
+            let key = parse_private_key_file(&key).map_err(Error::from)?;
+            let key = keyd.add(group_id, name, key).await?;
+
+            to_value(KeyInfo::from(&key))
+        }
+        "store.init" => {
+            let Passphrase { passphrase } = params(params_)?;
+            keyd.init_master(&passphrase).await?;
+
+            Ok(Value::Null)
+        }
+        "store.unlock" => {
+            let Passphrase { passphrase } = params(params_)?;
+            keyd.unlock_master(&passphrase).await?;
+
+            Ok(Value::Null)
+        }
+        "store.lock" => {
+            keyd.lock_master();
+
+            Ok(Value::Null)
+        }
+        "key.remove" => {
+            let IdParams { id } = params(params_)?;
+            keyd.remove(id).await?;
+
+            Ok(Value::Null)
+        }
+        "key.policy.get" => {
+            let IdParams { id } = params(params_)?;
+            let item = keyd
+                .list_items(None)
+                .await?
+                .into_iter()
+                .find(|it| it.id == id)
+                .ok_or(Error::KeyNotfound)?;
+
+            let policy = keyd.key_policies().await?.remove(&id);
+            let effective = keyd.policy(&item).await?;
+
+            to_value(PolicyInfo {
+                policy: policy.map(|it| it.to_string()),
+                effective: effective.map(|it| it.to_string()),
+            })
+        }
+        "key.policy.set" => {
+            let SetPolicy { id, policy } = params(params_)?;
+            keyd.set_key_policy(id, parse_policy(policy)?).await?;
+
+            Ok(Value::Null)
+        }
+        "key.cert.list" => {
+            let ListCerts { key_id } = params(params_)?;
+            let certs = match key_id {
+                Some(key_id) => keyd.list_key_certs(key_id).await?,
+                None => keyd.list_certs().await?,
+            };
+
+            to_value(certs.iter().map(CertInfo::from).collect::<Vec<_>>())
+        }
+        "key.cert.add" => {
+            let AddCert { certificate } = params(params_)?;
+            let cert = parse_cert_line(certificate.trim()).map_err(|e| RpcError {
+                code: INVALID_PARAMS,
+                message: e.to_string(),
+            })?;
+
+            to_value(CertInfo::from(&keyd.add_cert(&cert).await?))
+        }
+        "key.cert.remove" => {
+            let IdParams { id } = params(params_)?;
+            keyd.remove_cert(id).await?;
+
+            Ok(Value::Null)
+        }
+        "key.expire" => {
+            let SetExpiry { id, expires_at } = params(params_)?;
+            keyd.set_key_expiry(id, expires_at).await?;
+
+            Ok(Value::Null)
+        }
+        "group.list" => to_value(keyd.list_groups().await?),
+        "group.add" => {
+            let AddGroup { name } = params(params_)?;
+
+            to_value(keyd.create_group(name).await?)
+        }
+        "group.rename" => {
+            let RenameGroup { id, name } = params(params_)?;
+            keyd.rename_group(id, name).await?;
+
+            Ok(Value::Null)
+        }
+        "group.remove" => {
+            let IdParams { id } = params(params_)?;
+            keyd.delete_group(id).await?;
+
+            Ok(Value::Null)
+        }
+        "group.policy.get" => {
+            let IdParams { id } = params(params_)?;
+            let policy = keyd.group_policies().await?.remove(&id);
+
+            to_value(policy.map(|it| it.to_string()))
+        }
+        "group.policy.set" => {
+            let SetPolicy { id, policy } = params(params_)?;
+            keyd.set_group_policy(id, parse_policy(policy)?).await?;
+
+            Ok(Value::Null)
+        }
+        "group.host.list" => to_value(keyd.group_hosts().await?),
+        "group.host.add" | "group.host.remove" => {
+            let GroupHost { id, fingerprint } = params(params_)?;
+            if method == "group.host.add" {
+                keyd.add_group_host(id, fingerprint).await?;
+            } else {
+                keyd.remove_group_host(id, fingerprint).await?;
+            }
+
+            Ok(Value::Null)
+        }
+        "group.program.list" => to_value(keyd.group_programs().await?),
+        "group.program.add" | "group.program.remove" => {
+            let GroupProgram { id, exe } = params(params_)?;
+            if method == "group.program.add" {
+                keyd.add_group_program(id, exe).await?;
+            } else {
+                keyd.remove_group_program(id, exe).await?;
+            }
+
+            Ok(Value::Null)
+        }
+        "key.tag" | "key.untag" => {
+            let TagKey { id, tag } = params(params_)?;
+            if method == "key.tag" {
+                keyd.tag_key(id, tag).await?;
+            } else {
+                keyd.untag_key(id, tag).await?;
+            }
+
+            Ok(Value::Null)
+        }
+        "tag.policy.get" => {
+            let TagParams { tag } = params(params_)?;
+            let policy = keyd.tag_policies().await?.remove(&tag);
+
+            to_value(policy.map(|it| it.to_string()))
+        }
+        "tag.policy.set" => {
+            let SetTagPolicy { tag, policy } = params(params_)?;
+            keyd.set_tag_policy(tag, parse_policy(policy)?).await?;
+
+            Ok(Value::Null)
+        }
+        "audit.list" => {
+            let ListAudit {
+                since,
+                until,
+                fingerprint,
+            } = params(params_)?;
+
+            to_value(
+                keyd.audit_events(since, until, fingerprint.as_deref())
+                    .await?,
+            )
+        }
+        "audit.prune" => {
+            let PruneAudit { before } = params(params_)?;
+
+            to_value(keyd.prune_audit(before).await?)
+        }
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("unknown method {}", method),
+        }),
+    }
+}
+
+/// serve `method` on running agent, key store methods are passed to `call_keyd`
+async fn call_agent(agent: &mut KeyDAgent, method: &str, params_: Value) -> RpcResult {
+    match method {
+        "agent.status" => to_value(AgentStatus {
+            locked: agent.is_locked(),
+            store_locked: agent.keyd.is_store_locked().await?,
+            temporary_keys: agent.temporary_keys(),
+            pending_approvals: agent.pending_approvals(),
+        }),
+        "agent.lock" | "agent.unlock" => {
+            let Passphrase { passphrase } = params(params_)?;
+            let passphrase = passphrase.into_bytes().into();
+            let request = if method == "agent.lock" {
+                Request::Lock(passphrase)
+            } else {
+                Request::Unlock(passphrase)
+            };
+
+            let reply = agent.process(request).await?;
+            if reply.get(4).copied() != Some(ServerResponseType::SshAgentSuccess as u8) {
+                return Err(Error::AgentFailure.into());
+            }
+
+            Ok(Value::Null)
+        }
+        _ => call_keyd(&mut agent.keyd, method, params_).await,
+    }
+}
+
+/// serve JSON-RPC requests, one JSON object per line, from processes of agent owner
+pub async fn serve(agent: KeyDAgent, listener: UnixListener) -> Result<()> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                match Peer::from_stream(&stream) {
+                    Ok(peer) if peer.uid == unsafe { libc::geteuid() } => {}
+                    Ok(peer) => {
+                        warn!("reject control connection from {}", peer);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "reject control connection without peer credentials: {:?}",
+                            e
+                        );
+                        continue;
+                    }
+                }
+
+                let agent = agent.clone();
+                tokio::spawn(async move {
+                    handle(stream, agent).await.ok();
+                });
+            }
+            Err(e) => {
+                error!("failed accept control connection: {:?}", e);
+            }
+        }
+    }
+}
+
+async fn handle(stream: UnixStream, mut agent: KeyDAgent) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<RpcRequest>(&line) {
+            Ok(request) => {
+                let result = call_agent(&mut agent, &request.method, request.params).await;
+                RpcResponse::new(request.id, result)
+            }
+            Err(e) => RpcResponse::new(
+                Value::Null,
+                Err(RpcError {
+                    code: PARSE_ERROR,
+                    message: e.to_string(),
+                }),
+            ),
+        };
+
+        let mut buf = serde_json::to_vec(&response)?;
+        buf.push(b'\n');
+        writer.write_all(&buf).await?;
+    }
+
+    Ok(())
+}
+
+/// client of daemon control socket
+#[derive(Debug)]
+pub struct ControlClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl ControlClient {
+    pub async fn connect(path: impl AsRef<Path>) -> Result<ControlClient> {
+        let (reader, writer) = UnixStream::connect(path).await?.into_split();
+
+        Ok(ControlClient {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+        })
+    }
+
+    pub async fn call<T: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = RpcRequest {
+            jsonrpc: "2.0".into(),
+            id: id.into(),
+            method: method.into(),
+            params,
+        };
+        let mut buf = serde_json::to_vec(&request)?;
+        buf.push(b'\n');
+        self.writer.write_all(&buf).await?;
+
+        let line = self
+            .lines
+            .next_line()
+            .await?
+            .ok_or_else(|| Error::IOError(std::io::ErrorKind::UnexpectedEof.into()))?;
+        let response: RpcResponse = serde_json::from_str(&line)?;
+
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(Error::Control(error.message)),
+            (result, None) => Ok(serde_json::from_value(result.unwrap_or(Value::Null))?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::agent::KeyDAgent;
+    use crate::approve::AllowAll;
+    use crate::control::{serve, AgentStatus, ControlClient, KeyInfo};
+    use crate::keyd::KeyD;
+    use crate::store::KeyStore;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn control_roundtrip() -> anyhow::Result<()> {
+        let store = KeyStore::new("sqlite::memory:").await?;
+        store.init().await?;
+        let agent = KeyDAgent::new(KeyD::new(store)?, Arc::new(AllowAll), false)?;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("control.sock");
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        tokio::spawn(serve(agent, listener));
+
+        let mut client = ControlClient::connect(&path).await?;
+
+        let id: i64 = client.call("group.add", json!({"name": "work"})).await?;
+        client
+            .call::<()>(
+                "group.policy.set",
+                json!({"id": id, "policy": "connection"}),
+            )
+            .await?;
+        let policy: Option<String> = client.call("group.policy.get", json!({ "id": id })).await?;
+        assert_eq!(policy.as_deref(), Some("connection"));
+
+        let keys: Vec<KeyInfo> = client.call("key.list", json!(null)).await?;
+        assert!(keys.is_empty());
+
+        client
+            .call::<()>(
+                "group.host.add",
+                json!({"id": id, "fingerprint": "SHA256:host"}),
+            )
+            .await?;
+        let hosts: HashMap<i64, Vec<String>> = client.call("group.host.list", json!(null)).await?;
+        assert_eq!(hosts[&id], vec!["SHA256:host"]);
+
+        client
+            .call::<()>("agent.lock", json!({"passphrase": "secret"}))
+            .await?;
+        let status: AgentStatus = client.call("agent.status", json!(null)).await?;
+        assert!(status.locked);
+
+        assert!(client
+            .call::<()>("group.policy.set", json!({"id": id, "policy": "sometimes"}))
+            .await
+            .is_err());
+        assert!(client
+            .call::<()>("no.such.method", json!(null))
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Formatter};
+
+use openssl::pkcs5::scrypt;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::error::{Error, Result};
+use crate::store::models::MasterKeyParams;
+
+/// prefix of private keys sealed by master key, rows without it are plaintext PEM
+const SEALED_PREFIX: &str = "keyd-sealed:";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// scrypt cost of new master keys, N = 2^17, r = 8, p = 1 takes 128 MiB
+#[cfg(not(test))]
+const DEFAULT_LOG_N: i64 = 17;
+#[cfg(test)]
+const DEFAULT_LOG_N: i64 = 10;
+const DEFAULT_R: i64 = 8;
+const DEFAULT_P: i64 = 1;
+
+/// associated data of verifier, so it cannot be swapped with a key row
+const VERIFIER_AAD: &[u8] = b"keyd master key";
+
+/// AES-256-GCM key derived from master passphrase by scrypt
+pub struct MasterKey([u8; 32]);
+
+impl MasterKey {
+    /// derive new master key with random salt, return params to store
+    pub fn create(passphrase: &str) -> Result<(MasterKey, MasterKeyParams)> {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand_bytes(&mut salt)?;
+
+        let mut params = MasterKeyParams {
+            salt,
+            log_n: DEFAULT_LOG_N,
+            r: DEFAULT_R,
+            p: DEFAULT_P,
+            verifier: String::new(),
+        };
+        let key = MasterKey::derive(passphrase, &params)?;
+        params.verifier = key.seal(&params.salt, VERIFIER_AAD)?;
+
+        Ok((key, params))
+    }
+
+    /// derive master key with stored params, fail if passphrase does not match verifier
+    pub fn unlock(passphrase: &str, params: &MasterKeyParams) -> Result<MasterKey> {
+        let key = MasterKey::derive(passphrase, params)?;
+        match key.open(&params.verifier, VERIFIER_AAD) {
+            Ok(salt) if salt == params.salt => Ok(key),
+            _ => Err(Error::WrongPassphrase),
+        }
+    }
+
+    /// params come from database, out of range values fail instead of overflowing
+    fn derive(passphrase: &str, params: &MasterKeyParams) -> Result<MasterKey> {
+        let cost = || {
+            let n = 1u64.checked_shl(u32::try_from(params.log_n).ok()?)?;
+            let r = u64::try_from(params.r).ok()?;
+            let p = u64::try_from(params.p).ok()?;
+            // scrypt needs 128 * N * r bytes, leave headroom for openssl bookkeeping
+            let max_mem = 128u64
+                .checked_mul(n)?
+                .checked_mul(r)?
+                .checked_mul(p)?
+                .checked_add(1024 * 1024)?;
+
+            Some((n, r, p, max_mem))
+        };
+        let (n, r, p, max_mem) = cost().ok_or(Error::Decrypt)?;
+
+        let mut key = [0u8; 32];
+        scrypt(
+            passphrase.as_bytes(),
+            &params.salt,
+            n,
+            r,
+            p,
+            max_mem,
+            &mut key,
+        )?;
+
+        Ok(MasterKey(key))
+    }
+
+    /// encrypt `plaintext` with fresh nonce, `aad` binds ciphertext to its row
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand_bytes(&mut nonce)?;
+
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(&nonce),
+            aad,
+            plaintext,
+            &mut tag,
+        )?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed.extend_from_slice(&tag);
+
+        Ok(format!(
+            "{}{}",
+            SEALED_PREFIX,
+            openssl::base64::encode_block(&sealed)
+        ))
+    }
+
+    /// decrypt value produced by `seal` with same `aad`
+    pub fn open(&self, sealed: &str, aad: &[u8]) -> Result<Vec<u8>> {
+        let sealed = sealed.strip_prefix(SEALED_PREFIX).ok_or(Error::Decrypt)?;
+        let sealed = openssl::base64::decode_block(sealed).map_err(|_| Error::Decrypt)?;
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(Error::Decrypt);
+        }
+
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            aad,
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| Error::Decrypt)
+    }
+}
+
+impl Debug for MasterKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MasterKey(..)")
+    }
+}
+
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        for b in self.0.iter_mut() {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+    }
+}
+
+/// whether stored private key is sealed by master key
+pub fn is_sealed(private_key: &str) -> bool {
+    private_key.starts_with(SEALED_PREFIX)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crypt::{is_sealed, MasterKey};
+    use crate::error::Error;
+    use crate::store::models::MasterKeyParams;
+
+    #[test]
+    fn seal_and_unlock() -> anyhow::Result<()> {
+        // cheap params, default cost is too slow for tests
+        let mut params = MasterKeyParams {
+            salt: b"0123456789abcdef".to_vec(),
+            log_n: 10,
+            r: 8,
+            p: 1,
+            verifier: String::new(),
+        };
+        let key = MasterKey::derive("secret", &params)?;
+        params.verifier = key.seal(&params.salt, super::VERIFIER_AAD)?;
+
+        let sealed = key.seal(b"private key", b"SHA256:a")?;
+        assert!(is_sealed(&sealed));
+        assert_ne!(sealed, key.seal(b"private key", b"SHA256:a")?);
+        assert_eq!(key.open(&sealed, b"SHA256:a")?, b"private key");
+        assert!(matches!(
+            key.open(&sealed, b"SHA256:b"),
+            Err(Error::Decrypt)
+        ));
+
+        let key = MasterKey::unlock("secret", &params)?;
+        assert_eq!(key.open(&sealed, b"SHA256:a")?, b"private key");
+        assert!(matches!(
+            MasterKey::unlock("wrong", &params),
+            Err(Error::WrongPassphrase)
+        ));
+
+        // corrupted cost from database
+        for (log_n, r) in [(64, 8), (-1, 8), (20, i64::MAX)].iter() {
+            let params = MasterKeyParams {
+                log_n: *log_n,
+                r: *r,
+                ..params.clone()
+            };
+            assert!(matches!(
+                MasterKey::unlock("secret", &params),
+                Err(Error::Decrypt)
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use bytes::{Buf, Bytes, BytesMut};
+use libsshkey::SSHBuffer;
+
+use crate::cert::{Certificate, CERT_SUFFIX};
+use crate::session::SessionBind;
+
+/// OpenSSH key constraint extension added by `ssh-add -h`
+pub const EXTENSION_RESTRICT_DESTINATION: &str = "restrict-destination-v00@openssh.com";
+
+/// one side of a destination constraint, a host identified by its keys
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HopSpec {
+    /// user name pattern, only meaningful for `to` hop
+    pub user: String,
+
+    /// host name for display, empty for origin host
+    pub hostname: String,
+    pub keys: Vec<HopKey>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HopKey {
+    /// public key blob
+    pub key: Bytes,
+
+    /// key is a CA, matching host certificates signed by it
+    pub is_ca: bool,
+}
+
+/// allowed hop `from` -> `to`, see PROTOCOL.agent in OpenSSH
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DestConstraint {
+    pub from: HopSpec,
+    pub to: HopSpec,
+}
+
+/// parse contents of `restrict-destination-v00@openssh.com` constraint
+pub fn parse_dest_constraints(contents: impl AsRef<[u8]>) -> anyhow::Result<Vec<DestConstraint>> {
+    let mut buf = buffer(contents)?;
+    let mut constraints = Vec::new();
+
+    while !buf.is_empty() {
+        let mut constraint = buffer(buf.get_string()?)?;
+        let from = parse_hop(constraint.get_string()?)?;
+        let to = parse_hop(constraint.get_string()?)?;
+        let _reserved = constraint.get_string()?;
+
+        if to.hostname.is_empty() {
+            anyhow::bail!("destination constraint without destination host");
+        }
+        if from.hostname.is_empty() != from.keys.is_empty() {
+            anyhow::bail!("invalid origin of destination constraint");
+        }
+
+        constraints.push(DestConstraint { from, to });
+    }
+
+    if constraints.is_empty() {
+        anyhow::bail!("empty destination constraint");
+    }
+
+    Ok(constraints)
+}
+
+fn parse_hop(input: Bytes) -> anyhow::Result<HopSpec> {
+    let mut buf = buffer(input)?;
+
+    let user = string(buf.get_string()?)?;
+    let hostname = string(buf.get_string()?)?;
+    let _reserved = buf.get_string()?;
+
+    let mut keys = Vec::new();
+    while !buf.is_empty() {
+        let key = buf.get_string()?;
+        if buf.is_empty() {
+            anyhow::bail!("truncated destination constraint key");
+        }
+        let is_ca = buf.get_u8() != 0;
+
+        keys.push(HopKey { key, is_ca });
+    }
+
+    Ok(HopSpec {
+        user,
+        hostname,
+        keys,
+    })
+}
+
+/// whether host key blob is one of hop keys, or a host certificate signed by a hop CA
+fn match_hop_key(hop: &HopSpec, host_key: &[u8]) -> bool {
+    let cert = if host_key.len() > 4 && ends_with_cert_type(host_key) {
+        Certificate::parse(host_key).ok()
+    } else {
+        None
+    };
+
+    hop.keys.iter().any(|it| match (&cert, it.is_ca) {
+        (None, false) => &*it.key == host_key,
+        (Some(cert), true) => it.key == cert.signature_key,
+        _ => false,
+    })
+}
+
+fn ends_with_cert_type(blob: &[u8]) -> bool {
+    let mut buf = match buffer(blob) {
+        Ok(buf) => buf,
+        Err(_) => return false,
+    };
+
+    match buf.get_string() {
+        Ok(ty) => ty.ends_with(CERT_SUFFIX.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+/// whether any constraint permits hop `from` -> `to`, `None` on `from` is the origin
+/// host and `None` on `to` asks for onward forwarding from `from`.
+fn permitted_hop(
+    constraints: &[DestConstraint],
+    from: Option<&[u8]>,
+    to: Option<&[u8]>,
+    user: Option<&str>,
+) -> bool {
+    constraints.iter().any(|it| {
+        let from_ok = match from {
+            None => it.from.hostname.is_empty(),
+            Some(from) => match_hop_key(&it.from, from),
+        };
+        let to_ok = match to {
+            None => true,
+            Some(to) => match_hop_key(&it.to, to),
+        };
+        let user_ok = match user {
+            Some(user) if !it.to.user.is_empty() => match_pattern(user, &it.to.user),
+            _ => true,
+        };
+
+        from_ok && to_ok && user_ok
+    })
+}
+
+/// check key with destination constraints against session binds of a connection.
+///
+/// `user` is set when signing a user authentication request for the last hop.
+/// connections without any bind are local use and always permitted.
+pub fn identity_permitted(
+    constraints: &[DestConstraint],
+    binds: &[SessionBind],
+    user: Option<&str>,
+) -> bool {
+    if constraints.is_empty() || binds.is_empty() {
+        return true;
+    }
+
+    for (i, bind) in binds.iter().enumerate() {
+        let from = if i == 0 {
+            None
+        } else {
+            Some(&*binds[i - 1].host_key)
+        };
+
+        let last = i == binds.len() - 1;
+        let test_user = if last {
+            if bind.forwarding && user.is_some() {
+                // signing on a forwarding hop
+                return false;
+            }
+            user
+        } else {
+            if !bind.forwarding {
+                // forwarding through an authentication bind
+                return false;
+            }
+            None
+        };
+
+        if !permitted_hop(constraints, from, Some(&*bind.host_key), test_user) {
+            return false;
+        }
+    }
+
+    // forwarded further, some constraint must start from last host
+    match binds.last() {
+        Some(last) if last.forwarding => {
+            permitted_hop(constraints, Some(&*last.host_key), None, None)
+        }
+        _ => true,
+    }
+}
+
+/// shell style pattern match supporting `*` and `?`, comma separated alternatives
+/// and `!` negation, as OpenSSH `match_pattern_list`
+pub fn match_pattern(input: &str, patterns: &str) -> bool {
+    let mut matched = false;
+
+    for pattern in patterns.split(',') {
+        let (negate, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+
+        if glob(input.as_bytes(), pattern.as_bytes()) {
+            if negate {
+                return false;
+            }
+            matched = true;
+        }
+    }
+
+    matched
+}
+
+fn glob(input: &[u8], pattern: &[u8]) -> bool {
+    match (pattern.first(), input.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(input, &pattern[1..]) || (!input.is_empty() && glob(&input[1..], pattern))
+        }
+        (Some(b'?'), Some(_)) => glob(&input[1..], &pattern[1..]),
+        (Some(p), Some(c)) if p == c => glob(&input[1..], &pattern[1..]),
+        _ => false,
+    }
+}
+
+fn buffer(input: impl AsRef<[u8]>) -> anyhow::Result<SSHBuffer> {
+    Ok(SSHBuffer::from_bytes_mut(BytesMut::from(input.as_ref()))?)
+}
+
+fn string(input: Bytes) -> anyhow::Result<String> {
+    Ok(String::from_utf8(input.to_vec())?)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::destination::{identity_permitted, match_pattern, DestConstraint, HopKey, HopSpec};
+    use crate::session::SessionBind;
+
+    fn bind(key: &'static [u8], forwarding: bool) -> SessionBind {
+        SessionBind {
+            host_key: Bytes::from_static(key),
+            host_fingerprint: String::new(),
+            session_id: Bytes::from_static(key),
+            forwarding,
+        }
+    }
+
+    fn hop(user: &str, hostname: &str, key: Option<&'static [u8]>) -> HopSpec {
+        HopSpec {
+            user: user.into(),
+            hostname: hostname.into(),
+            keys: key
+                .into_iter()
+                .map(|it| HopKey {
+                    key: Bytes::from_static(it),
+                    is_ca: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(match_pattern("alice", "alice"));
+        assert!(match_pattern("alice", "bob,al*"));
+        assert!(match_pattern("alice", "?lice"));
+        assert!(!match_pattern("alice", "al*,!alice"));
+        assert!(!match_pattern("alice", "bob"));
+    }
+
+    #[test]
+    fn hop_chain() {
+        // local -> bastion -> target
+        let constraints = vec![
+            DestConstraint {
+                from: hop("", "", None),
+                to: hop("", "bastion", Some(&b"bastion-key"[..])),
+            },
+            DestConstraint {
+                from: hop("", "bastion", Some(&b"bastion-key"[..])),
+                to: hop("deploy", "target", Some(&b"target-key"[..])),
+            },
+        ];
+
+        // local use
+        assert!(identity_permitted(&constraints, &[], None));
+
+        // authenticate to bastion
+        let binds = vec![bind(b"bastion-key", false)];
+        assert!(identity_permitted(&constraints, &binds, Some("anyone")));
+
+        // forwarded through bastion to target
+        let binds = vec![bind(b"bastion-key", true), bind(b"target-key", false)];
+        assert!(identity_permitted(&constraints, &binds, Some("deploy")));
+        assert!(!identity_permitted(&constraints, &binds, Some("root")));
+
+        // forwarded to unknown host
+        let binds = vec![bind(b"bastion-key", true), bind(b"other-key", false)];
+        assert!(!identity_permitted(&constraints, &binds, None));
+
+        // target directly is not allowed
+        let binds = vec![bind(b"target-key", false)];
+        assert!(!identity_permitted(&constraints, &binds, None));
+
+        // no onward forwarding from target
+        let binds = vec![bind(b"bastion-key", true), bind(b"target-key", true)];
+        assert!(!identity_permitted(&constraints, &binds, None));
+    }
+}
@@ -8,6 +8,12 @@ pub enum Error {
     #[error("request key not found")]
     KeyNotfound,
 
+    #[error("request key expired")]
+    KeyExpired,
+
+    #[error("DSA keys are disabled, use `--allow-dss` to enable")]
+    DssDisabled,
+
     #[error("{}", _0)]
     Generic(#[from] anyhow::Error),
 
@@ -17,8 +23,50 @@ pub enum Error {
     #[error("{}", _0)]
     StoreError(#[from] crate::store::StoreError),
 
+    #[error("openssl error: {}", _0)]
+    OpenSSL(#[from] openssl::error::ErrorStack),
+
     #[error("IO error: {}", _0)]
     IOError(#[from] std::io::Error),
+
+    #[error("invalid agent message length: {}", _0)]
+    InvalidMessageLength(usize),
+
+    #[error("agent refused request")]
+    AgentFailure,
+
+    #[error("unknown approval backend: {}", _0)]
+    UnknownApprover(String),
+
+    #[error("approval failed: {}", _0)]
+    Approval(String),
+
+    #[error("agent already running on {}", _0.display())]
+    AgentRunning(std::path::PathBuf),
+
+    #[error("insecure socket path {}: {}", _0.display(), _1)]
+    InsecureSocket(std::path::PathBuf, &'static str),
+
+    #[error("json error: {}", _0)]
+    Json(#[from] serde_json::Error),
+
+    #[error("control request failed: {}", _0)]
+    Control(String),
+
+    #[error("key store is locked, run `keyd unlock`")]
+    StoreLocked,
+
+    #[error("key store has no master passphrase, run `keyd init`")]
+    StoreNotEncrypted,
+
+    #[error("key store master passphrase already set")]
+    StoreEncrypted,
+
+    #[error("wrong master passphrase")]
+    WrongPassphrase,
+
+    #[error("failed to decrypt private key")]
+    Decrypt,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,162 @@
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::{Error, Result};
+
+/// max size of a single agent message, same limit as OpenSSH `AGENT_MAX_LEN`
+pub const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+/// length-prefixed packet reader/writer over an agent connection.
+///
+/// each packet is a big endian `u32` length followed by that many bytes,
+/// a single read may carry part of a packet or several packets at once.
+#[derive(Debug)]
+pub struct Framed<S> {
+    stream: S,
+    buf: BytesMut,
+}
+
+impl<S> Framed<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(stream: S) -> Framed<S> {
+        Framed {
+            stream,
+            buf: BytesMut::with_capacity(4096),
+        }
+    }
+
+    /// read next complete packet, including the 4-byte length prefix.
+    ///
+    /// return `None` when peer closed the connection between packets.
+    pub async fn read_packet(&mut self) -> Result<Option<BytesMut>> {
+        loop {
+            if let Some(packet) = split_packet(&mut self.buf)? {
+                return Ok(Some(packet));
+            }
+
+            let r = self.stream.read_buf(&mut self.buf).await?;
+            if r == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+
+                return Err(Error::IOError(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
+
+    /// wait until peer closes the connection, e.g. while a request is being processed.
+    ///
+    /// bytes received meanwhile are kept for `read_packet`.
+    pub async fn closed(&mut self) -> Result<()> {
+        loop {
+            if self.buf.len() >= 4 + MAX_MESSAGE_SIZE {
+                // peer pipelined too much, stop reading until current request is done
+                return std::future::pending().await;
+            }
+
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// write a whole packet to peer
+    pub async fn write_packet(&mut self, packet: impl AsRef<[u8]>) -> Result<()> {
+        self.stream.write_all(packet.as_ref()).await?;
+        self.stream.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// split one complete packet off the front of `buf`, `None` if more bytes are needed.
+fn split_packet(buf: &mut BytesMut) -> Result<Option<BytesMut>> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+
+    let len = (&buf[0..4]).get_u32() as usize;
+    if len == 0 || len > MAX_MESSAGE_SIZE {
+        return Err(Error::InvalidMessageLength(len));
+    }
+
+    if buf.len() < 4 + len {
+        buf.reserve(4 + len - buf.len());
+        return Ok(None);
+    }
+
+    Ok(Some(buf.split_to(4 + len)))
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::framed::{Framed, MAX_MESSAGE_SIZE};
+
+    fn packet(body: &[u8]) -> Vec<u8> {
+        let mut buf = (body.len() as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    #[tokio::test]
+    async fn split_and_merged_reads() -> Result<()> {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut framed = Framed::new(server);
+
+        let large = vec![0x42u8; 1000];
+        let mut wire = packet(&large);
+        wire.extend(packet(&[11]));
+        wire.extend(packet(&[11]));
+
+        tokio::spawn(async move {
+            for chunk in wire.chunks(7) {
+                client.write_all(chunk).await.unwrap();
+            }
+        });
+
+        assert_eq!(&framed.read_packet().await?.unwrap()[..], &packet(&large)[..]);
+        assert_eq!(&framed.read_packet().await?.unwrap()[..], &packet(&[11])[..]);
+        assert_eq!(&framed.read_packet().await?.unwrap()[..], &packet(&[11])[..]);
+        assert!(framed.read_packet().await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_oversize() -> Result<()> {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut framed = Framed::new(server);
+
+        client
+            .write_all(&((MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes())
+            .await?;
+
+        assert!(framed.read_packet().await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn closed_keeps_pipelined() -> Result<()> {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut framed = Framed::new(server);
+
+        client.write_all(&packet(&[11])).await?;
+        drop(client);
+
+        framed.closed().await?;
+        assert_eq!(
+            &framed.read_packet().await?.unwrap()[..],
+            &packet(&[11])[..]
+        );
+        assert!(framed.read_packet().await?.is_none());
+
+        Ok(())
+    }
+}
@@ -2,7 +2,19 @@
 extern crate tracing;
 
 pub mod agent;
+pub mod approve;
+pub mod cert;
+pub mod client;
+pub mod config;
+pub mod control;
+pub mod crypt;
+pub mod destination;
 pub mod error;
+pub mod framed;
 pub mod keyd;
 pub mod parse;
+pub mod peer;
+pub mod session;
+pub mod socket;
 pub mod store;
+pub mod verify;
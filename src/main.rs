@@ -1,5 +1,6 @@
 use tracing_subscriber::EnvFilter;
 
+use keyd::config::Config;
 use keyd::keyd::KeyD;
 use keyd::store::KeyStore;
 
@@ -7,16 +8,30 @@ mod cli;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = cli::app().get_matches();
+
+    let mut config = Config::load(args.value_of_os("config").map(std::path::Path::new))?;
+    if let Some(database) = args.value_of_os("database") {
+        config.database = database.into();
+    }
+
     tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("keyd=INFO".parse()?))
+        .with_env_filter(
+            EnvFilter::from_default_env().add_directive(format!("keyd={}", config.log).parse()?),
+        )
+        // stdout is reserved for `eval`-able output of `keyd agent`
+        .with_writer(std::io::stderr)
         .init();
 
-    let store = KeyStore::new("sqlite://key.db").await?;
+    let store = KeyStore::new(&config.database_url()?).await?;
+    if let Some(args) = args.subcommand_matches("db") {
+        return cli::run_db(args, &store).await;
+    }
     store.init().await?;
 
     let keyd = KeyD::new(store)?;
 
-    cli::run(keyd).await?;
+    cli::run(&args, keyd, &config).await?;
 
     Ok(())
 }
@@ -0,0 +1,70 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use tokio::net::UnixStream;
+
+/// process on the other end of an agent connection, from `SO_PEERCRED`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Peer {
+    pub pid: Option<i32>,
+    pub uid: u32,
+
+    /// command name from `/proc/<pid>/comm`
+    pub command: Option<String>,
+
+    /// resolved executable from `/proc/<pid>/exe`
+    pub exe: Option<PathBuf>,
+
+    /// arguments from `/proc/<pid>/cmdline`, joined by space
+    pub cmdline: Option<String>,
+}
+
+impl Peer {
+    pub fn from_stream(stream: &UnixStream) -> std::io::Result<Peer> {
+        let cred = stream.peer_cred()?;
+        let pid = cred.pid();
+
+        let proc = |name: &str| pid.map(|pid| format!("/proc/{}/{}", pid, name));
+        let command = proc("comm")
+            .and_then(|it| std::fs::read_to_string(it).ok())
+            .map(|it| it.trim_end().to_owned());
+        let exe = proc("exe").and_then(|it| std::fs::read_link(it).ok());
+        let cmdline = proc("cmdline")
+            .and_then(|it| std::fs::read(it).ok())
+            .map(|it| {
+                it.split(|c| *c == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+
+        Ok(Peer {
+            pid,
+            uid: cred.uid(),
+            command,
+            exe,
+            cmdline,
+        })
+    }
+
+    /// executable path as string, for matching program rules
+    pub fn exe_path(&self) -> Option<&str> {
+        self.exe.as_ref()?.to_str()
+    }
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.exe, &self.command) {
+            (Some(exe), _) => write!(f, "{:?}", exe)?,
+            (None, Some(command)) => write!(f, "{:?}", command)?,
+            (None, None) => write!(f, "unknown process")?,
+        }
+
+        match self.pid {
+            Some(pid) => write!(f, " (pid {}, uid {})", pid, self.uid),
+            None => write!(f, " (uid {})", self.uid),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt::{Display, Formatter};
+
+use bytes::{Buf, Bytes, BytesMut};
+use libsshkey::key::HashType;
+use libsshkey::SSHBuffer;
+
+use crate::verify::verify_signature;
+
+/// OpenSSH extension binding agent connection to a ssh session
+pub const EXTENSION_SESSION_BIND: &str = "session-bind@openssh.com";
+
+/// max session binds recorded per connection, same as OpenSSH
+pub const MAX_SESSION_BINDS: usize = 16;
+
+/// one hop recorded by `session-bind@openssh.com`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SessionBind {
+    /// public key blob of server host key
+    pub host_key: Bytes,
+
+    /// SHA256 fingerprint of `host_key`
+    pub host_fingerprint: String,
+
+    pub session_id: Bytes,
+
+    /// agent connection is forwarded to this host, not only used to authenticate to it
+    pub forwarding: bool,
+}
+
+impl SessionBind {
+    /// parse extension contents and verify host key signature over session id
+    pub fn parse(contents: impl AsRef<[u8]>) -> anyhow::Result<SessionBind> {
+        let mut buf = SSHBuffer::from_bytes_mut(BytesMut::from(contents.as_ref()))?;
+
+        let host_key = buf.get_string()?;
+        let session_id = buf.get_string()?;
+        let signature = buf.get_string()?;
+        if buf.is_empty() {
+            anyhow::bail!("truncated session bind");
+        }
+        let forwarding = buf.get_u8() != 0;
+
+        if !verify_signature(&host_key, &session_id, &signature)? {
+            anyhow::bail!("bad host key signature on session bind");
+        }
+
+        let host_fingerprint =
+            libsshkey::key::parse_public_blob(host_key.clone())?.fingerprint(HashType::SHA256)?;
+
+        Ok(SessionBind {
+            host_key,
+            host_fingerprint,
+            session_id,
+            forwarding,
+        })
+    }
+}
+
+const SSH2_MSG_USERAUTH_REQUEST: u8 = 50;
+
+/// ssh user authentication request being signed, see RFC 4252 section 7
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UserAuth {
+    pub session_id: Bytes,
+    pub user: String,
+    pub service: String,
+    pub method: String,
+    pub algorithm: String,
+    pub key: Bytes,
+
+    /// server host key of `publickey-hostbound-v00@openssh.com` method
+    pub host_key: Option<Bytes>,
+}
+
+impl UserAuth {
+    /// parse sign data as user authentication request, `None` if it isn't one
+    pub fn parse(data: impl AsRef<[u8]>) -> Option<UserAuth> {
+        let mut buf = SSHBuffer::from_bytes_mut(BytesMut::from(data.as_ref())).ok()?;
+
+        let session_id = buf.get_string().ok()?;
+        if buf.is_empty() || buf.get_u8() != SSH2_MSG_USERAUTH_REQUEST {
+            return None;
+        }
+
+        let string = |it: Bytes| String::from_utf8(it.to_vec()).ok();
+        let user = string(buf.get_string().ok()?)?;
+        let service = string(buf.get_string().ok()?)?;
+        let method = string(buf.get_string().ok()?)?;
+        if buf.is_empty() || buf.get_u8() == 0 {
+            return None;
+        }
+        let algorithm = string(buf.get_string().ok()?)?;
+        let key = buf.get_string().ok()?;
+
+        let host_key = match &*method {
+            "publickey" => None,
+            "publickey-hostbound-v00@openssh.com" => Some(buf.get_string().ok()?),
+            _ => return None,
+        };
+
+        if !buf.is_empty() {
+            return None;
+        }
+
+        Some(UserAuth {
+            session_id,
+            user,
+            service,
+            method,
+            algorithm,
+            key,
+            host_key,
+        })
+    }
+}
+
+/// record `bind` on a connection's bind chain, following OpenSSH rules
+pub fn record_bind(binds: &mut Vec<SessionBind>, bind: SessionBind) -> anyhow::Result<()> {
+    if let Some(exist) = binds.iter().find(|it| it.session_id == bind.session_id) {
+        if exist.host_key != bind.host_key {
+            anyhow::bail!("session id already bound to another host key");
+        }
+
+        return Ok(());
+    }
+
+    if let Some(last) = binds.last() {
+        if !last.forwarding {
+            anyhow::bail!("connection already bound for authentication");
+        }
+    }
+
+    if binds.len() >= MAX_SESSION_BINDS {
+        anyhow::bail!("too many session binds");
+    }
+
+    binds.push(bind);
+
+    Ok(())
+}
+
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+
+/// data signed by `ssh-keygen -Y sign`, see PROTOCOL.sshsig in OpenSSH
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SshSig {
+    pub namespace: String,
+    pub hash_algorithm: String,
+}
+
+impl SshSig {
+    /// parse sign data as SSHSIG signed data, `None` if it isn't one
+    pub fn parse(data: impl AsRef<[u8]>) -> Option<SshSig> {
+        let data = data.as_ref().strip_prefix(SSHSIG_MAGIC)?;
+        let mut buf = SSHBuffer::from_bytes_mut(BytesMut::from(data)).ok()?;
+
+        let string = |it: Bytes| String::from_utf8(it.to_vec()).ok();
+        let namespace = string(buf.get_string().ok()?)?;
+        let _reserved = buf.get_string().ok()?;
+        let hash_algorithm = string(buf.get_string().ok()?)?;
+        let _hash = buf.get_string().ok()?;
+
+        if !buf.is_empty() {
+            return None;
+        }
+
+        Some(SshSig {
+            namespace,
+            hash_algorithm,
+        })
+    }
+}
+
+/// what a sign request is for, decoded from data being signed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SignPurpose {
+    UserAuth(UserAuth),
+    SshSig(SshSig),
+}
+
+impl SignPurpose {
+    pub fn decode(data: impl AsRef<[u8]>) -> Option<SignPurpose> {
+        let data = data.as_ref();
+
+        UserAuth::parse(data)
+            .map(SignPurpose::UserAuth)
+            .or_else(|| SshSig::parse(data).map(SignPurpose::SshSig))
+    }
+}
+
+impl Display for SignPurpose {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // names come from client, quoted to keep control characters out of prompts
+        match self {
+            SignPurpose::UserAuth(auth) => write!(
+                f,
+                "ssh authentication as user {:?} for {:?} using {:?}",
+                auth.user, auth.service, auth.algorithm
+            ),
+            SignPurpose::SshSig(sig) => write!(f, "{:?} signature (SSHSIG)", sig.namespace),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libsshkey::SSHBuffer;
+
+    use crate::session::{SignPurpose, SshSig};
+
+    #[test]
+    fn decode_purpose() {
+        let mut auth = SSHBuffer::empty().unwrap();
+        auth.put_string(&b"session id"[..]).unwrap();
+        let mut data = auth.to_vec();
+        data.push(50);
+        let mut auth = SSHBuffer::empty().unwrap();
+        auth.put_string("alice").unwrap();
+        auth.put_string("ssh-connection").unwrap();
+        auth.put_string("publickey").unwrap();
+        data.extend(auth.to_vec());
+        data.push(1);
+        let mut auth = SSHBuffer::empty().unwrap();
+        auth.put_string("ssh-ed25519").unwrap();
+        auth.put_string(&b"key"[..]).unwrap();
+        data.extend(auth.to_vec());
+
+        match SignPurpose::decode(&data) {
+            Some(SignPurpose::UserAuth(auth)) => {
+                assert_eq!(auth.user, "alice");
+                assert_eq!(auth.service, "ssh-connection");
+                assert_eq!(auth.algorithm, "ssh-ed25519");
+            }
+            other => panic!("unexpected purpose {:?}", other),
+        }
+
+        let mut sig = SSHBuffer::empty().unwrap();
+        sig.put_string("git").unwrap();
+        sig.put_string(&b""[..]).unwrap();
+        sig.put_string("sha512").unwrap();
+        sig.put_string(&[0u8; 64][..]).unwrap();
+        let mut data = b"SSHSIG".to_vec();
+        data.extend(sig.to_vec());
+
+        assert_eq!(
+            SignPurpose::decode(&data),
+            Some(SignPurpose::SshSig(SshSig {
+                namespace: "git".into(),
+                hash_algorithm: "sha512".into(),
+            }))
+        );
+
+        assert_eq!(SignPurpose::decode(b"random data"), None);
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs::DirBuilder;
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use tokio::net::UnixListener;
+
+use crate::error::{Error, Result};
+
+/// socket file name inside runtime directory
+const SOCKET_NAME: &str = "agent.sock";
+
+/// per-user runtime directory of agent socket, `$XDG_RUNTIME_DIR/keyd`
+/// or `keyd-<uid>` in temp dir
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("keyd"),
+        _ => std::env::temp_dir().join(format!("keyd-{}", unsafe { libc::geteuid() })),
+    }
+}
+
+/// default agent socket path in `runtime_dir`
+pub fn default_socket_path() -> PathBuf {
+    runtime_dir().join(SOCKET_NAME)
+}
+
+/// agent socket of a single process in `runtime_dir`, for agents running a command
+pub fn process_socket_path() -> PathBuf {
+    runtime_dir().join(format!("agent.{}.sock", std::process::id()))
+}
+
+/// control socket next to agent socket, `<agent socket>.ctl`
+pub fn control_socket_path(agent_socket: &Path) -> PathBuf {
+    let mut path = agent_socket.as_os_str().to_owned();
+    path.push(".ctl");
+
+    path.into()
+}
+
+/// create `dir` with mode 0700, or check an existing one is private to current user
+pub fn ensure_private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() {
+        return Err(Error::InsecureSocket(dir.into(), "not a directory"));
+    }
+    if meta.uid() != unsafe { libc::geteuid() } {
+        return Err(Error::InsecureSocket(dir.into(), "owned by another user"));
+    }
+    if meta.mode() & 0o077 != 0 {
+        return Err(Error::InsecureSocket(
+            dir.into(),
+            "accessible by other users",
+        ));
+    }
+
+    Ok(())
+}
+
+/// remove socket left by a dead agent at `path`, fail if an agent still listens on it
+fn remove_stale(path: &Path) -> Result<()> {
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if !meta.file_type().is_socket() {
+        return Err(Error::InsecureSocket(
+            path.into(),
+            "exists and is not a socket",
+        ));
+    }
+
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(Error::AgentRunning(path.into())),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            warn!("remove stale socket {}", path.display());
+            std::fs::remove_file(path)?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// bind agent socket at `path` with mode 0600, replacing a stale socket.
+///
+/// parent directory is created private when missing, and checked private when
+/// inside `runtime_dir`, so socket is not reachable by others before chmod.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        if !dir.exists() || dir.starts_with(runtime_dir()) {
+            ensure_private_dir(dir)?;
+        }
+    }
+    remove_stale(path)?;
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use std::path::Path;
+
+    use crate::error::Error;
+    use crate::socket::{bind, control_socket_path};
+
+    #[tokio::test]
+    async fn stale_and_live_socket() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("keyd-test-{}", std::process::id()));
+        let path = dir.join("agent.sock");
+
+        let listener = bind(&path)?;
+        assert_eq!(std::fs::metadata(&dir)?.permissions().mode() & 0o777, 0o700);
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+
+        // live agent keeps its socket
+        assert!(matches!(bind(&path), Err(Error::AgentRunning(_))));
+
+        // socket of dead agent is replaced
+        drop(listener);
+        let _listener = bind(&path)?;
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn control_socket_next_to_agent() {
+        assert_eq!(
+            control_socket_path(Path::new("/tmp/x")),
+            Path::new("/tmp/x.ctl")
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use std::str::FromStr;
 
-use sqlx::{sqlite::SqliteConnectOptions, sqlite::SqlitePoolOptions, SqlitePool};
+use sqlx::{
+    sqlite::SqliteConnectOptions, sqlite::SqlitePoolOptions, Sqlite, SqlitePool, Transaction,
+};
 
-use crate::store::models::{KeyGroup, KeyItem};
+use crate::crypt::is_sealed;
+use crate::store::models::{
+    AuditEvent, GroupHost, GroupProgram, KeyCert, KeyGroup, KeyItem, MasterKeyParams,
+};
 
 pub mod models;
 
@@ -19,30 +24,136 @@ pub enum StoreError {
 
     #[error("key id {} not exist", _0)]
     KeyIdNotExist(i64),
+
+    #[error("certificate id {} not exist", _0)]
+    CertIdNotExist(i64),
+
+    #[error(
+        "database schema version {} is newer than supported version {}",
+        _0,
+        _1
+    )]
+    DatabaseTooNew(i64, i64),
+
+    #[error("key with fingerprint {} already exists", _0)]
+    DuplicateFingerprint(String),
+
+    #[error("group name {} already exists", _0)]
+    DuplicateGroupName(String),
+
+    #[error("migration {} blocked by existing data: {}", _0, _1.join("; "))]
+    MigrationConflict(i64, Vec<String>),
 }
 
 type Result<T, E = StoreError> = std::result::Result<T, E>;
 
-#[derive(Clone, Debug)]
-pub struct KeyStore {
-    pool: SqlitePool,
-}
+/// table recording applied migrations
+const VERSION_SQL: &str = r#"
+    create table if not exists db_version (
+        version   integer
+    );
+"#;
 
-impl KeyStore {
-    pub async fn new(url: impl AsRef<str>) -> Result<Self> {
-        let pool = SqlitePoolOptions::new()
-            .connect_with(SqliteConnectOptions::from_str(url.as_ref())?.create_if_missing(true))
+/// run `migration` in `tx` unless its check finds conflicting rows
+async fn apply_migration(tx: &mut Transaction<'_, Sqlite>, migration: &Migration) -> Result<()> {
+    const SQL: &'static str = r#"
+        insert into db_version (version) values (?);
+    "#;
+
+    if let Some(check) = migration.check {
+        let conflicts = sqlx::query_as::<_, (String,)>(check)
+            .fetch_all(&mut *tx)
             .await?;
+        if !conflicts.is_empty() {
+            return Err(StoreError::MigrationConflict(
+                migration.version,
+                conflicts.into_iter().map(|(it,)| it).collect(),
+            ));
+        }
+    }
 
-        Ok(KeyStore { pool })
+    let _ = sqlx::query(migration.sql).execute(&mut *tx).await?;
+    let _ = sqlx::query(SQL)
+        .bind(migration.version)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
+
+/// replace private keys in `tx` still stored plaintext by `seal` result, return their number
+async fn seal_keys<E: From<StoreError>>(
+    tx: &mut Transaction<'_, Sqlite>,
+    seal: impl Fn(&KeyItem) -> Result<String, E>,
+) -> Result<u64, E> {
+    const SQL: &'static str = r#"
+        select id, name, fingerprint, public_key, private_key, group_id, key_type, created_at, last_used_at, sign_count, expires_at from key_items;
+    "#;
+    const KEY_SQL: &'static str = r#"
+        update key_items set private_key = ? where id = ?;
+    "#;
+
+    let items = sqlx::query_as::<_, KeyItem>(SQL)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(StoreError::from)?;
+
+    let mut count = 0;
+    for item in items.iter().filter(|it| !is_sealed(&it.private_key)) {
+        let _ = sqlx::query(KEY_SQL)
+            .bind(seal(item)?)
+            .bind(item.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(StoreError::from)?;
+        count += 1;
     }
 
-    pub async fn init(&self) -> Result<()> {
-        const INIT_SQL: &'static str = r#"
-            create table if not exists db_version (
-                version   integer
-            );
+    Ok(count)
+}
+
+/// sqlite constraint broken by a statement
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Violation {
+    Unique,
+    ForeignKey,
+}
+
+/// constraint violation from sqlite extended result code of `e`
+fn violation(e: &sqlx::Error) -> Option<Violation> {
+    const SQLITE_CONSTRAINT_FOREIGNKEY: &str = "787";
+    const SQLITE_CONSTRAINT_PRIMARYKEY: &str = "1555";
+    const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+
+    match e {
+        sqlx::Error::Database(e) => match e.code().as_deref() {
+            Some(SQLITE_CONSTRAINT_UNIQUE) | Some(SQLITE_CONSTRAINT_PRIMARYKEY) => {
+                Some(Violation::Unique)
+            }
+            Some(SQLITE_CONSTRAINT_FOREIGNKEY) => Some(Violation::ForeignKey),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// schema change applied once, in order of `version`
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
 
+    /// query describing rows that would break migration, one text column per conflict
+    pub check: Option<&'static str>,
+}
+
+/// schema history, tables use `if not exists` since databases before versioning have some already
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create key groups and items",
+        sql: r#"
             create table if not exists key_groups (
                 id        integer primary key autoincrement,
                 name      text
@@ -57,9 +168,281 @@ impl KeyStore {
                 group_id       integer,
                 key_type       text
             );
-        "#;
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 2,
+        description: "create key certificates",
+        sql: r#"
+            create table if not exists key_certs (
+                id             integer primary key autoincrement,
+                key_id         integer,
+                cert_type      text,
+                identity       text,
+                certificate    blob,
+                valid_before   integer
+            );
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 3,
+        description: "create group destination hosts and key destination constraints",
+        sql: r#"
+            create table if not exists group_hosts (
+                id             integer primary key autoincrement,
+                group_id       integer,
+                fingerprint    text
+            );
+
+            create table if not exists key_destinations (
+                key_id         integer primary key,
+                constraints    blob
+            );
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 4,
+        description: "create key and group approval policies",
+        sql: r#"
+            create table if not exists key_policies (
+                key_id         integer primary key,
+                policy         text
+            );
+
+            create table if not exists group_policies (
+                group_id       integer primary key,
+                policy         text
+            );
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 5,
+        description: "create group programs",
+        sql: r#"
+            create table if not exists group_programs (
+                id             integer primary key autoincrement,
+                group_id       integer,
+                exe            text
+            );
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 6,
+        description: "create master key",
+        sql: r#"
+            create table if not exists master_key (
+                id             integer primary key check (id = 1),
+                salt           blob,
+                log_n          integer,
+                r              integer,
+                p              integer,
+                verifier       text
+            );
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 7,
+        description: "add foreign keys and unique fingerprints and group names",
+        sql: r#"
+            create unique index key_groups_name on key_groups (name);
+
+            create table key_items_new (
+                id             integer primary key autoincrement,
+                name           text,
+                fingerprint    text not null,
+                public_key     text,
+                private_key    text,
+                group_id       integer not null references key_groups (id),
+                key_type       text
+            );
+            insert into key_items_new (id, name, fingerprint, public_key, private_key, group_id, key_type)
+                select id, name, fingerprint, public_key, private_key, group_id, key_type from key_items;
+            drop table key_items;
+            alter table key_items_new rename to key_items;
+            create unique index key_items_fingerprint on key_items (fingerprint);
+
+            create table key_certs_new (
+                id             integer primary key autoincrement,
+                key_id         integer not null references key_items (id) on delete cascade,
+                cert_type      text,
+                identity       text,
+                certificate    blob,
+                valid_before   integer
+            );
+            insert into key_certs_new (id, key_id, cert_type, identity, certificate, valid_before)
+                select id, key_id, cert_type, identity, certificate, valid_before from key_certs
+                where key_id in (select id from key_items);
+            drop table key_certs;
+            alter table key_certs_new rename to key_certs;
+
+            create table key_destinations_new (
+                key_id         integer primary key references key_items (id) on delete cascade,
+                constraints    blob
+            );
+            insert into key_destinations_new (key_id, constraints)
+                select key_id, constraints from key_destinations
+                where key_id in (select id from key_items);
+            drop table key_destinations;
+            alter table key_destinations_new rename to key_destinations;
+
+            create table key_policies_new (
+                key_id         integer primary key references key_items (id) on delete cascade,
+                policy         text
+            );
+            insert into key_policies_new (key_id, policy)
+                select key_id, policy from key_policies
+                where key_id in (select id from key_items);
+            drop table key_policies;
+            alter table key_policies_new rename to key_policies;
+
+            create table group_hosts_new (
+                id             integer primary key autoincrement,
+                group_id       integer not null references key_groups (id) on delete cascade,
+                fingerprint    text
+            );
+            insert into group_hosts_new (id, group_id, fingerprint)
+                select id, group_id, fingerprint from group_hosts
+                where group_id in (select id from key_groups);
+            drop table group_hosts;
+            alter table group_hosts_new rename to group_hosts;
+
+            create table group_programs_new (
+                id             integer primary key autoincrement,
+                group_id       integer not null references key_groups (id) on delete cascade,
+                exe            text
+            );
+            insert into group_programs_new (id, group_id, exe)
+                select id, group_id, exe from group_programs
+                where group_id in (select id from key_groups);
+            drop table group_programs;
+            alter table group_programs_new rename to group_programs;
+
+            create table group_policies_new (
+                group_id       integer primary key references key_groups (id) on delete cascade,
+                policy         text
+            );
+            insert into group_policies_new (group_id, policy)
+                select group_id, policy from group_policies
+                where group_id in (select id from key_groups);
+            drop table group_policies;
+            alter table group_policies_new rename to group_policies;
+        "#,
+        // rows the new schema cannot hold, dropping them silently would lose data
+        check: Some(
+            r#"
+            select 'fingerprint ' || fingerprint || ' stored in keys ' || group_concat(id, ', ')
+                from key_items where fingerprint is not null
+                group by fingerprint having count(1) > 1
+            union all
+            select 'key ' || id || ' has no fingerprint'
+                from key_items where fingerprint is null
+            union all
+            select 'key ' || id || ' in missing group ' || ifnull(group_id, 'null')
+                from key_items where group_id is null or group_id not in (select id from key_groups)
+            union all
+            select 'group name ' || ifnull(name, 'null') || ' used by groups ' || group_concat(id, ', ')
+                from key_groups group by name having count(1) > 1
+            union all
+            select 'certificate ' || id || ' of missing key ' || ifnull(key_id, 'null')
+                from key_certs where key_id is null or key_id not in (select id from key_items)
+            union all
+            select 'destinations of missing key ' || ifnull(key_id, 'null')
+                from key_destinations where key_id is null or key_id not in (select id from key_items)
+            union all
+            select 'policy of missing key ' || ifnull(key_id, 'null')
+                from key_policies where key_id is null or key_id not in (select id from key_items)
+            union all
+            select 'host ' || id || ' of missing group ' || ifnull(group_id, 'null')
+                from group_hosts where group_id is null or group_id not in (select id from key_groups)
+            union all
+            select 'program ' || id || ' of missing group ' || ifnull(group_id, 'null')
+                from group_programs where group_id is null or group_id not in (select id from key_groups)
+            union all
+            select 'policy of missing group ' || ifnull(group_id, 'null')
+                from group_policies where group_id is null or group_id not in (select id from key_groups);
+            "#,
+        ),
+    },
+    Migration {
+        version: 8,
+        description: "record key creation, usage and expiry",
+        sql: r#"
+            alter table key_items add column created_at integer not null default 0;
+            alter table key_items add column last_used_at integer;
+            alter table key_items add column sign_count integer not null default 0;
+            alter table key_items add column expires_at integer;
+
+            -- creation of existing keys is unknown, count them as created now
+            update key_items set created_at = strftime('%s', 'now');
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 9,
+        description: "create audit events",
+        sql: r#"
+            create table audit_events (
+                id             integer primary key autoincrement,
+                time           integer not null,
+                operation      text not null,
+                fingerprint    text,
+                outcome        text,
+                peer_pid       integer,
+                peer_uid       integer,
+                peer_exe       text,
+                context        text
+            );
+            create index audit_events_time on audit_events (time);
+        "#,
+        check: None,
+    },
+    Migration {
+        version: 10,
+        description: "create key tags and tag policies",
+        sql: r#"
+            create table key_tags (
+                key_id         integer not null references key_items (id) on delete cascade,
+                tag            text not null,
+                primary key (key_id, tag)
+            );
+            create index key_tags_tag on key_tags (tag);
+
+            create table tag_policies (
+                tag            text primary key,
+                policy         text
+            );
+        "#,
+        check: None,
+    },
+];
+
+#[derive(Clone, Debug)]
+pub struct KeyStore {
+    pool: SqlitePool,
+}
+
+impl KeyStore {
+    pub async fn new(url: impl AsRef<str>) -> Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .connect_with(
+                SqliteConnectOptions::from_str(url.as_ref())?
+                    .create_if_missing(true)
+                    .foreign_keys(true),
+            )
+            .await?;
 
-        let _ = sqlx::query(INIT_SQL).execute(&self.pool).await?;
+        Ok(KeyStore { pool })
+    }
+
+    /// migrate schema to latest version and create default group
+    pub async fn init(&self) -> Result<()> {
+        self.migrate().await?;
 
         {
             const Q_SQL: &'static str = r#"
@@ -78,6 +461,69 @@ impl KeyStore {
         Ok(())
     }
 
+    /// applied schema version, 0 for new databases and those before versioning.
+    ///
+    /// read only, `db_version` table is created by `migrate`.
+    pub async fn version(&self) -> Result<i64> {
+        const EXIST_SQL: &'static str = r#"
+            select count(1) from sqlite_master where type = 'table' and name = 'db_version';
+        "#;
+        const SQL: &'static str = r#"
+            select max(version) from db_version;
+        "#;
+
+        let (exist,) = sqlx::query_as::<_, (i64,)>(EXIST_SQL)
+            .fetch_one(&self.pool)
+            .await?;
+        if exist == 0 {
+            return Ok(0);
+        }
+
+        let (version,) = sqlx::query_as::<_, (Option<i64>,)>(SQL)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(version.unwrap_or_default())
+    }
+
+    /// migrations not yet applied, fail if database is newer than this binary
+    pub async fn pending_migrations(&self) -> Result<Vec<&'static Migration>> {
+        let version = self.version().await?;
+        let latest = MIGRATIONS.last().map_or(0, |it| it.version);
+        if version > latest {
+            return Err(StoreError::DatabaseTooNew(version, latest));
+        }
+
+        Ok(MIGRATIONS
+            .iter()
+            .filter(|it| it.version > version)
+            .collect())
+    }
+
+    /// apply pending migrations, each in own transaction with its version record
+    pub async fn migrate(&self) -> Result<()> {
+        let _ = sqlx::query(VERSION_SQL).execute(&self.pool).await?;
+        for migration in self.pending_migrations().await? {
+            let mut tx = self.pool.begin().await?;
+            apply_migration(&mut tx, migration).await?;
+            tx.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    /// apply pending migrations and roll back, to find conflicts without changing database
+    pub async fn check_migrations(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let _ = sqlx::query(VERSION_SQL).execute(&mut tx).await?;
+        for migration in self.pending_migrations().await? {
+            apply_migration(&mut tx, migration).await?;
+        }
+        tx.rollback().await?;
+
+        Ok(())
+    }
+
     pub async fn create_group(&self, name: impl AsRef<str>) -> Result<i64> {
         const SQL: &'static str = r#"
             insert into key_groups (name) values (?);
@@ -86,28 +532,29 @@ impl KeyStore {
         let r = sqlx::query(SQL)
             .bind(name.as_ref())
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::Unique) => StoreError::DuplicateGroupName(name.as_ref().to_owned()),
+                _ => e.into(),
+            })?;
 
         Ok(r.last_insert_rowid())
     }
 
     pub async fn delete_group(&self, id: i64) -> Result<()> {
-        const Q_SQL: &'static str = r#"
-            select count(1) from key_items where group_id = ?;
-        "#;
         const DEL_SQL: &'static str = r#"
             delete from key_groups where id = ?;
         "#;
 
-        let (items,) = sqlx::query_as::<_, (i64,)>(Q_SQL)
+        // keys block deletion, hosts, programs and policies of group are deleted by cascade
+        let _ = sqlx::query(DEL_SQL)
             .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
-        if items != 0 {
-            return Err(StoreError::GroupNotEmpty);
-        }
-
-        let _ = sqlx::query(DEL_SQL).bind(id).execute(&self.pool).await?;
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::ForeignKey) => StoreError::GroupNotEmpty,
+                _ => e.into(),
+            })?;
 
         Ok(())
     }
@@ -117,11 +564,20 @@ impl KeyStore {
             update key_groups set name = ? where id = ?;
         "#;
 
-        let _ = sqlx::query(SQL)
+        let r = sqlx::query(SQL)
             .bind(new_name.as_ref())
             .bind(id)
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::Unique) => {
+                    StoreError::DuplicateGroupName(new_name.as_ref().to_owned())
+                }
+                _ => e.into(),
+            })?;
+        if r.rows_affected() == 0 {
+            return Err(StoreError::GroupIdNotExist(id));
+        }
 
         Ok(())
     }
@@ -136,6 +592,98 @@ impl KeyStore {
         Ok(results)
     }
 
+    /// restrict keys of group to destination host key fingerprint
+    pub async fn add_group_host(&self, group_id: i64, fingerprint: impl AsRef<str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert into group_hosts (group_id, fingerprint) values (?, ?);
+        "#;
+
+        let _group = self
+            .get_group(group_id)
+            .await?
+            .ok_or(StoreError::GroupIdNotExist(group_id))?;
+
+        let _ = sqlx::query(SQL)
+            .bind(group_id)
+            .bind(fingerprint.as_ref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_group_host(
+        &self,
+        group_id: i64,
+        fingerprint: impl AsRef<str>,
+    ) -> Result<()> {
+        const SQL: &'static str = r#"
+            delete from group_hosts where group_id = ? and fingerprint = ?;
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(group_id)
+            .bind(fingerprint.as_ref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_group_hosts(&self) -> Result<Vec<GroupHost>> {
+        const SQL: &'static str = r#"
+            select group_id, fingerprint from group_hosts;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    /// restrict keys of group to client executable path
+    pub async fn add_group_program(&self, group_id: i64, exe: impl AsRef<str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert into group_programs (group_id, exe) values (?, ?);
+        "#;
+
+        let _group = self
+            .get_group(group_id)
+            .await?
+            .ok_or(StoreError::GroupIdNotExist(group_id))?;
+
+        let _ = sqlx::query(SQL)
+            .bind(group_id)
+            .bind(exe.as_ref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_group_program(&self, group_id: i64, exe: impl AsRef<str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            delete from group_programs where group_id = ? and exe = ?;
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(group_id)
+            .bind(exe.as_ref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_group_programs(&self) -> Result<Vec<GroupProgram>> {
+        const SQL: &'static str = r#"
+            select group_id, exe from group_programs;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
     pub async fn get_group(&self, id: i64) -> Result<Option<KeyGroup>> {
         const SQL: &'static str = r#"
             select id, name from key_groups where id = ?;
@@ -153,7 +701,7 @@ impl KeyStore {
 impl KeyStore {
     pub async fn add_key(&self, group_id: i64, key: &KeyItem) -> Result<i64> {
         const SQL: &'static str = r#"
-            insert into key_items (name, fingerprint, public_key, private_key, group_id, key_type) values (?, ?, ?, ?, ?, ?);
+            insert into key_items (name, fingerprint, public_key, private_key, group_id, key_type, created_at, expires_at) values (?, ?, ?, ?, ?, ?, ?, ?);
         "#;
 
         let r = sqlx::query(SQL)
@@ -163,8 +711,17 @@ impl KeyStore {
             .bind(&key.private_key)
             .bind(group_id)
             .bind(&key.key_type)
+            .bind(key.created_at)
+            .bind(key.expires_at)
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::Unique) => {
+                    StoreError::DuplicateFingerprint(key.fingerprint.clone())
+                }
+                Some(Violation::ForeignKey) => StoreError::GroupIdNotExist(group_id),
+                None => e.into(),
+            })?;
 
         Ok(r.last_insert_rowid())
     }
@@ -174,6 +731,7 @@ impl KeyStore {
             delete from key_items where id = ?;
         "#;
 
+        // certificates, destinations and policy of key are deleted by cascade
         let _ = sqlx::query(SQL).bind(id).execute(&self.pool).await?;
 
         Ok(())
@@ -184,16 +742,18 @@ impl KeyStore {
             update key_items set group_id = ? where id = ?;
         "#;
 
-        let _group = self
-            .get_group(group_id)
-            .await?
-            .ok_or(StoreError::GroupIdNotExist(group_id))?;
-
-        let _ = sqlx::query(SQL)
+        let r = sqlx::query(SQL)
             .bind(group_id)
             .bind(key_id)
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::ForeignKey) => StoreError::GroupIdNotExist(group_id),
+                _ => e.into(),
+            })?;
+        if r.rows_affected() == 0 {
+            return Err(StoreError::KeyIdNotExist(key_id));
+        }
 
         Ok(())
     }
@@ -209,7 +769,8 @@ impl KeyStore {
               public_key = ?,
               private_key = ?,
               group_id = ?,
-              key_type = ?
+              key_type = ?,
+              expires_at = ?
             where id = ?;
         "#;
         let (count,) = sqlx::query_as::<_, (i64,)>(Q_SQL)
@@ -227,16 +788,26 @@ impl KeyStore {
             .bind(&key.private_key)
             .bind(key.group_id.unwrap_or_default())
             .bind(&key.key_type)
+            .bind(key.expires_at)
             .bind(id)
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::Unique) => {
+                    StoreError::DuplicateFingerprint(key.fingerprint.clone())
+                }
+                Some(Violation::ForeignKey) => {
+                    StoreError::GroupIdNotExist(key.group_id.unwrap_or_default())
+                }
+                None => e.into(),
+            })?;
 
         Ok(())
     }
 
     pub async fn list_group_keys(&self, id: i64) -> Result<Vec<KeyItem>> {
         const SQL: &'static str = r#"
-            select id, name, fingerprint, public_key, private_key, group_id, key_type from key_items where group_id = ?;
+            select id, name, fingerprint, public_key, private_key, group_id, key_type, created_at, last_used_at, sign_count, expires_at from key_items where group_id = ?;
         "#;
 
         let results = sqlx::query_as(SQL).bind(id).fetch_all(&self.pool).await?;
@@ -246,7 +817,7 @@ impl KeyStore {
 
     pub async fn list_keys(&self) -> Result<Vec<KeyItem>> {
         const SQL: &'static str = r#"
-            select id, name, fingerprint, public_key, private_key, group_id, key_type from key_items;
+            select id, name, fingerprint, public_key, private_key, group_id, key_type, created_at, last_used_at, sign_count, expires_at from key_items;
         "#;
 
         let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
@@ -256,7 +827,7 @@ impl KeyStore {
 
     pub async fn get_key_by_name(&self, name: impl AsRef<str>) -> Result<Option<KeyItem>> {
         const SQL: &'static str = r#"
-            select id, name, fingerprint, public_key, private_key, group_id, key_type from key_items where name = ?;
+            select id, name, fingerprint, public_key, private_key, group_id, key_type, created_at, last_used_at, sign_count, expires_at from key_items where name = ?;
         "#;
 
         let key = sqlx::query_as(SQL)
@@ -265,28 +836,648 @@ impl KeyStore {
             .await?;
         Ok(key)
     }
-    pub async fn get_key_by_fingerprint(
+    /// params of master key, `None` when private keys are not encrypted
+    pub async fn get_master_key(&self) -> Result<Option<MasterKeyParams>> {
+        const SQL: &'static str = r#"
+            select salt, log_n, r, p, verifier from master_key where id = 1;
+        "#;
+
+        let params = sqlx::query_as(SQL).fetch_optional(&self.pool).await?;
+        Ok(params)
+    }
+
+    /// store master key params and seal plaintext private keys with `seal`, in one
+    /// transaction, so no key added meanwhile stays plaintext
+    pub async fn init_master_key<E: From<StoreError>>(
         &self,
-        fingerprint: impl AsRef<str>,
-    ) -> Result<Option<KeyItem>> {
+        params: &MasterKeyParams,
+        seal: impl Fn(&KeyItem) -> Result<String, E>,
+    ) -> Result<(), E> {
         const SQL: &'static str = r#"
-            select id, name, fingerprint, public_key, private_key, group_id, key_type from key_items where fingerprint = ?;
+            insert into master_key (id, salt, log_n, r, p, verifier) values (1, ?, ?, ?, ?, ?);
         "#;
 
-        let key = sqlx::query_as(SQL)
-            .bind(fingerprint.as_ref())
-            .fetch_optional(&self.pool)
+        let mut tx = self.pool.begin().await.map_err(StoreError::from)?;
+        // written first, holds sqlite write lock until commit
+        let _ = sqlx::query(SQL)
+            .bind(&params.salt)
+            .bind(params.log_n)
+            .bind(params.r)
+            .bind(params.p)
+            .bind(&params.verifier)
+            .execute(&mut tx)
+            .await
+            .map_err(StoreError::from)?;
+        seal_keys(&mut tx, seal).await?;
+        tx.commit().await.map_err(StoreError::from)?;
+
+        Ok(())
+    }
+
+    /// seal private keys still stored plaintext with `seal`, return their number
+    pub async fn seal_plaintext_keys<E: From<StoreError>>(
+        &self,
+        seal: impl Fn(&KeyItem) -> Result<String, E>,
+    ) -> Result<u64, E> {
+        let mut tx = self.pool.begin().await.map_err(StoreError::from)?;
+        let count = seal_keys(&mut tx, seal).await?;
+        tx.commit().await.map_err(StoreError::from)?;
+
+        Ok(count)
+    }
+
+    /// count successful signature of key at `now`
+    pub async fn record_sign(&self, id: i64, now: i64) -> Result<()> {
+        const SQL: &'static str = r#"
+            update key_items set last_used_at = ?, sign_count = sign_count + 1 where id = ?;
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(now)
+            .bind(id)
+            .execute(&self.pool)
             .await?;
-        Ok(key)
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use anyhow::Result;
+    /// set unix time key expires at, `None` to keep forever
+    pub async fn set_key_expiry(&self, id: i64, expires_at: Option<i64>) -> Result<()> {
+        const SQL: &'static str = r#"
+            update key_items set expires_at = ? where id = ?;
+        "#;
 
-    use crate::store::models::KeyGroup;
-    use crate::store::KeyStore;
+        let r = sqlx::query(SQL)
+            .bind(expires_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if r.rows_affected() == 0 {
+            return Err(StoreError::KeyIdNotExist(id));
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_key_by_fingerprint(
+        &self,
+        fingerprint: impl AsRef<str>,
+    ) -> Result<Option<KeyItem>> {
+        const SQL: &'static str = r#"
+            select id, name, fingerprint, public_key, private_key, group_id, key_type, created_at, last_used_at, sign_count, expires_at from key_items where fingerprint = ?;
+        "#;
+
+        let key = sqlx::query_as(SQL)
+            .bind(fingerprint.as_ref())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(key)
+    }
+}
+
+impl KeyStore {
+    /// set `restrict-destination-v00@openssh.com` constraints of key, `None` to clear
+    pub async fn set_key_destinations(
+        &self,
+        key_id: i64,
+        constraints: Option<&[u8]>,
+    ) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert or replace into key_destinations (key_id, constraints) values (?, ?);
+        "#;
+        const DEL_SQL: &'static str = r#"
+            delete from key_destinations where key_id = ?;
+        "#;
+
+        match constraints {
+            Some(constraints) => {
+                let _ = sqlx::query(SQL)
+                    .bind(key_id)
+                    .bind(constraints)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(|e| match violation(&e) {
+                        Some(Violation::ForeignKey) => StoreError::KeyIdNotExist(key_id),
+                        _ => e.into(),
+                    })?;
+            }
+            None => {
+                let _ = sqlx::query(DEL_SQL)
+                    .bind(key_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_key_destinations(&self) -> Result<Vec<(i64, Vec<u8>)>> {
+        const SQL: &'static str = r#"
+            select key_id, constraints from key_destinations;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    /// set approval policy of key, `None` to fall back to group policy
+    pub async fn set_key_policy(&self, key_id: i64, policy: Option<&str>) -> Result<()> {
+        const Q_SQL: &'static str = r#"
+            select count(1) from key_items where id = ?;
+        "#;
+        const SQL: &'static str = r#"
+            insert or replace into key_policies (key_id, policy) values (?, ?);
+        "#;
+        const DEL_SQL: &'static str = r#"
+            delete from key_policies where key_id = ?;
+        "#;
+
+        let (count,) = sqlx::query_as::<_, (i64,)>(Q_SQL)
+            .bind(key_id)
+            .fetch_one(&self.pool)
+            .await?;
+        if count != 1 {
+            return Err(StoreError::KeyIdNotExist(key_id));
+        }
+
+        match policy {
+            Some(policy) => {
+                let _ = sqlx::query(SQL)
+                    .bind(key_id)
+                    .bind(policy)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            None => {
+                let _ = sqlx::query(DEL_SQL)
+                    .bind(key_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_key_policies(&self) -> Result<Vec<(i64, String)>> {
+        const SQL: &'static str = r#"
+            select key_id, policy from key_policies;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    /// set approval policy of group, `None` to use agent default
+    pub async fn set_group_policy(&self, group_id: i64, policy: Option<&str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert or replace into group_policies (group_id, policy) values (?, ?);
+        "#;
+        const DEL_SQL: &'static str = r#"
+            delete from group_policies where group_id = ?;
+        "#;
+
+        let _group = self
+            .get_group(group_id)
+            .await?
+            .ok_or(StoreError::GroupIdNotExist(group_id))?;
+
+        match policy {
+            Some(policy) => {
+                let _ = sqlx::query(SQL)
+                    .bind(group_id)
+                    .bind(policy)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            None => {
+                let _ = sqlx::query(DEL_SQL)
+                    .bind(group_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_group_policies(&self) -> Result<Vec<(i64, String)>> {
+        const SQL: &'static str = r#"
+            select group_id, policy from group_policies;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    /// attach certificate to key, return existing id if already attached
+    pub async fn add_cert(&self, cert: &KeyCert) -> Result<i64> {
+        const Q_SQL: &'static str = r#"
+            select id from key_certs where certificate = ?;
+        "#;
+        const SQL: &'static str = r#"
+            insert into key_certs (key_id, cert_type, identity, certificate, valid_before) values (?, ?, ?, ?, ?);
+        "#;
+
+        let id = sqlx::query_as::<_, (i64,)>(Q_SQL)
+            .bind(&cert.certificate)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some((id,)) = id {
+            return Ok(id);
+        }
+
+        let r = sqlx::query(SQL)
+            .bind(cert.key_id)
+            .bind(&cert.cert_type)
+            .bind(&cert.identity)
+            .bind(&cert.certificate)
+            .bind(cert.valid_before)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::ForeignKey) => StoreError::KeyIdNotExist(cert.key_id),
+                _ => e.into(),
+            })?;
+
+        Ok(r.last_insert_rowid())
+    }
+
+    pub async fn remove_cert(&self, id: i64) -> Result<()> {
+        const SQL: &'static str = r#"
+            delete from key_certs where id = ?;
+        "#;
+
+        let r = sqlx::query(SQL).bind(id).execute(&self.pool).await?;
+        if r.rows_affected() == 0 {
+            return Err(StoreError::CertIdNotExist(id));
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_certs(&self) -> Result<Vec<KeyCert>> {
+        const SQL: &'static str = r#"
+            select id, key_id, cert_type, identity, certificate, valid_before from key_certs;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    pub async fn list_key_certs(&self, key_id: i64) -> Result<Vec<KeyCert>> {
+        const SQL: &'static str = r#"
+            select id, key_id, cert_type, identity, certificate, valid_before from key_certs where key_id = ?;
+        "#;
+
+        let results = sqlx::query_as(SQL)
+            .bind(key_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(results)
+    }
+}
+
+impl KeyStore {
+    /// tag key, tagging twice is a no-op
+    pub async fn add_key_tag(&self, key_id: i64, tag: impl AsRef<str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert or ignore into key_tags (key_id, tag) values (?, ?);
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(key_id)
+            .bind(tag.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match violation(&e) {
+                Some(Violation::ForeignKey) => StoreError::KeyIdNotExist(key_id),
+                _ => e.into(),
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn remove_key_tag(&self, key_id: i64, tag: impl AsRef<str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            delete from key_tags where key_id = ? and tag = ?;
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(key_id)
+            .bind(tag.as_ref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_key_tags(&self) -> Result<Vec<(i64, String)>> {
+        const SQL: &'static str = r#"
+            select key_id, tag from key_tags;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+
+    /// set approval policy of keys with tag, `None` to clear
+    pub async fn set_tag_policy(&self, tag: impl AsRef<str>, policy: Option<&str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            insert or replace into tag_policies (tag, policy) values (?, ?);
+        "#;
+        const DEL_SQL: &'static str = r#"
+            delete from tag_policies where tag = ?;
+        "#;
+
+        match policy {
+            Some(policy) => {
+                let _ = sqlx::query(SQL)
+                    .bind(tag.as_ref())
+                    .bind(policy)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            None => {
+                let _ = sqlx::query(DEL_SQL)
+                    .bind(tag.as_ref())
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_tag_policies(&self) -> Result<Vec<(String, String)>> {
+        const SQL: &'static str = r#"
+            select tag, policy from tag_policies;
+        "#;
+
+        let results = sqlx::query_as(SQL).fetch_all(&self.pool).await?;
+
+        Ok(results)
+    }
+}
+
+impl KeyStore {
+    pub async fn add_audit_event(&self, event: &AuditEvent) -> Result<i64> {
+        const SQL: &'static str = r#"
+            insert into audit_events (time, operation, fingerprint, outcome, peer_pid, peer_uid, peer_exe, context) values (?, ?, ?, ?, ?, ?, ?, ?);
+        "#;
+
+        let r = sqlx::query(SQL)
+            .bind(event.time)
+            .bind(&event.operation)
+            .bind(&event.fingerprint)
+            .bind(&event.outcome)
+            .bind(event.peer_pid)
+            .bind(event.peer_uid)
+            .bind(&event.peer_exe)
+            .bind(&event.context)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(r.last_insert_rowid())
+    }
+
+    /// set outcome of audit event once its request is processed
+    pub async fn set_audit_outcome(&self, id: i64, outcome: Option<&str>) -> Result<()> {
+        const SQL: &'static str = r#"
+            update audit_events set outcome = ? where id = ?;
+        "#;
+
+        let _ = sqlx::query(SQL)
+            .bind(outcome)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// audit events in `[since, until)`, optionally of one key, oldest first
+    pub async fn list_audit_events(
+        &self,
+        since: Option<i64>,
+        until: Option<i64>,
+        fingerprint: Option<&str>,
+    ) -> Result<Vec<AuditEvent>> {
+        const SQL: &'static str = r#"
+            select id, time, operation, fingerprint, outcome, peer_pid, peer_uid, peer_exe, context from audit_events
+            where (?1 is null or time >= ?1) and (?2 is null or time < ?2) and (?3 is null or fingerprint = ?3)
+            order by time, id;
+        "#;
+
+        let results = sqlx::query_as(SQL)
+            .bind(since)
+            .bind(until)
+            .bind(fingerprint)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(results)
+    }
+
+    /// delete audit events older than `before`, return number deleted
+    pub async fn prune_audit_events(&self, before: i64) -> Result<u64> {
+        const SQL: &'static str = r#"
+            delete from audit_events where time < ?;
+        "#;
+
+        let r = sqlx::query(SQL).bind(before).execute(&self.pool).await?;
+
+        Ok(r.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use crate::store::models::{KeyGroup, KeyItem, KeyType};
+    use crate::store::{KeyStore, StoreError, MIGRATIONS};
+
+    /// store on a file, migrations need a database surviving across connections
+    async fn file_store() -> Result<(tempfile::TempDir, KeyStore)> {
+        let dir = tempfile::tempdir()?;
+        let url = format!("sqlite://{}", dir.path().join("key.db").display());
+        let store = KeyStore::new(&url).await?;
+
+        Ok((dir, store))
+    }
+
+    /// new key item without key material
+    fn test_item(fingerprint: &str, group_id: i64) -> KeyItem {
+        KeyItem {
+            id: 0,
+            name: "key".into(),
+            fingerprint: fingerprint.into(),
+            public_key: String::new(),
+            private_key: String::new(),
+            key_type: KeyType::Ed25519,
+            group_id: Some(group_id),
+            created_at: 0,
+            last_used_at: None,
+            sign_count: 0,
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn migrations() -> Result<()> {
+        let (_dir, store) = file_store().await?;
+
+        assert_eq!(store.pending_migrations().await?.len(), MIGRATIONS.len());
+
+        // dry run leaves new database empty
+        store.check_migrations().await?;
+        assert_eq!(store.version().await?, 0);
+        let (tables,) = sqlx::query_as::<_, (i64,)>("select count(1) from sqlite_master;")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(tables, 0);
+
+        store.init().await?;
+        assert!(store.pending_migrations().await?.is_empty());
+        assert_eq!(store.version().await?, MIGRATIONS.last().unwrap().version);
+
+        // reopen is a no-op
+        store.init().await?;
+        assert_eq!(store.list_groups().await?.len(), 1);
+
+        sqlx::query("insert into db_version (version) values (1000);")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            store.init().await,
+            Err(StoreError::DatabaseTooNew(1000, _))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn migration_conflicts() -> Result<()> {
+        let (_dir, store) = file_store().await?;
+
+        // database from before versioning with duplicated group names
+        sqlx::query(MIGRATIONS[0].sql).execute(&store.pool).await?;
+        sqlx::query("insert into key_groups (name) values ('default'), ('work'), ('work');")
+            .execute(&store.pool)
+            .await?;
+
+        assert!(matches!(
+            store.check_migrations().await,
+            Err(StoreError::MigrationConflict(7, _))
+        ));
+        assert_eq!(store.version().await?, 0);
+
+        match store.init().await {
+            Err(StoreError::MigrationConflict(7, conflicts)) => {
+                assert_eq!(conflicts, vec!["group name work used by groups 2, 3"]);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(store.version().await?, 6);
+
+        // orphaned rows are reported, not dropped
+        sqlx::query("delete from key_groups where id = 3;")
+            .execute(&store.pool)
+            .await?;
+        sqlx::query("insert into key_policies (key_id, policy) values (42, 'deny');")
+            .execute(&store.pool)
+            .await?;
+        match store.init().await {
+            Err(StoreError::MigrationConflict(7, conflicts)) => {
+                assert_eq!(conflicts, vec!["policy of missing key 42"]);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        sqlx::query("delete from key_policies where key_id = 42;")
+            .execute(&store.pool)
+            .await?;
+        store.init().await?;
+        assert!(store.pending_migrations().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn constraints() -> Result<()> {
+        let (_dir, store) = file_store().await?;
+        store.init().await?;
+
+        let group_id = store.create_group("work").await?;
+        assert!(matches!(
+            store.create_group("work").await,
+            Err(StoreError::DuplicateGroupName(_))
+        ));
+
+        let item = test_item("SHA256:test", group_id);
+        let key_id = store.add_key(group_id, &item).await?;
+        assert!(matches!(
+            store.add_key(group_id, &item).await,
+            Err(StoreError::DuplicateFingerprint(_))
+        ));
+        assert!(matches!(
+            store.change_group(key_id + 1, group_id).await,
+            Err(StoreError::KeyIdNotExist(_))
+        ));
+        assert!(matches!(
+            store.change_group(key_id, group_id + 1).await,
+            Err(StoreError::GroupIdNotExist(_))
+        ));
+        assert!(matches!(
+            store.delete_group(group_id).await,
+            Err(StoreError::GroupNotEmpty)
+        ));
+
+        store.set_key_policy(key_id, Some("ask")).await?;
+        store.remove_key(key_id).await?;
+        assert!(store.list_key_policies().await?.is_empty());
+        store.delete_group(group_id).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn key_usage() -> Result<()> {
+        let store = KeyStore::new("sqlite::memory:").await?;
+        store.init().await?;
+
+        let item = KeyItem {
+            created_at: 50,
+            ..test_item("SHA256:test", 1)
+        };
+        let key_id = store.add_key(1, &item).await?;
+
+        store.record_sign(key_id, 100).await?;
+        store.record_sign(key_id, 200).await?;
+        let stored = store.get_key_by_fingerprint("SHA256:test").await?.unwrap();
+        assert_eq!(stored.created_at, 50);
+        assert_eq!((stored.last_used_at, stored.sign_count), (Some(200), 2));
+        assert!(!stored.is_expired(200));
+
+        store.set_key_expiry(key_id, Some(300)).await?;
+        let stored = store.get_key_by_fingerprint("SHA256:test").await?.unwrap();
+        assert!(!stored.is_expired(299));
+        assert!(stored.is_expired(300));
+
+        store.set_key_expiry(key_id, None).await?;
+        let stored = store.get_key_by_fingerprint("SHA256:test").await?.unwrap();
+        assert_eq!(stored.expires_at, None);
+
+        Ok(())
+    }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
     async fn group_ops() -> Result<()> {
@@ -1,12 +1,41 @@
 use libsshkey::key::Key as RawKey;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct KeyGroup {
     pub id: i64,
     pub name: String,
 }
 
+/// destination host key allowed for keys in group
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
+pub struct GroupHost {
+    pub group_id: i64,
+    pub fingerprint: String,
+}
+
+/// executable allowed to use keys in group
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
+pub struct GroupProgram {
+    pub group_id: i64,
+
+    /// absolute path of executable, as resolved by `/proc/<pid>/exe`
+    pub exe: String,
+}
+
+/// scrypt params and passphrase verifier of master key encrypting private keys
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
+pub struct MasterKeyParams {
+    pub salt: Vec<u8>,
+    pub log_n: i64,
+    pub r: i64,
+    pub p: i64,
+
+    /// salt sealed by master key, opens only with right passphrase
+    pub verifier: String,
+}
+
 #[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
 pub struct KeyItem {
     pub id: i64,
@@ -17,6 +46,37 @@ pub struct KeyItem {
     pub key_type: KeyType,
 
     pub group_id: Option<i64>,
+
+    /// unix time key was stored
+    pub created_at: i64,
+
+    /// unix time of last signature, `None` if never used
+    pub last_used_at: Option<i64>,
+    pub sign_count: i64,
+
+    /// unix time after which key is neither listed nor used
+    pub expires_at: Option<i64>,
+}
+
+impl KeyItem {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.map_or(false, |it| it <= now)
+    }
+}
+
+/// OpenSSH certificate attached to a stored key
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq)]
+pub struct KeyCert {
+    pub id: i64,
+    pub key_id: i64,
+    pub cert_type: String,
+
+    /// key id field of certificate
+    pub identity: String,
+    pub certificate: Vec<u8>,
+
+    /// unix time, clamped to `i64::MAX` for certificates valid forever
+    pub valid_before: i64,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Copy, sqlx::Type)]
@@ -27,6 +87,7 @@ pub enum KeyType {
     EcdsaP256,
     EcdsaP384,
     EcdsaP521,
+    Ed25519,
 }
 
 impl Display for KeyType {
@@ -37,10 +98,102 @@ impl Display for KeyType {
             KeyType::EcdsaP256 => write!(f, "ecdsa-p256"),
             KeyType::EcdsaP384 => write!(f, "ecdsa-p384"),
             KeyType::EcdsaP521 => write!(f, "ecdsa-p521"),
+            KeyType::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+/// approval policy of a key or group, key policy overrides group policy
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Policy {
+    /// ask before every signature
+    Ask,
+
+    /// sign without asking
+    Never,
+
+    /// ask once per agent connection
+    Connection,
+
+    /// ask once per given minutes
+    Interval(u32),
+
+    /// refuse to sign
+    Deny,
+}
+
+impl Display for Policy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Ask => write!(f, "ask"),
+            Policy::Never => write!(f, "never"),
+            Policy::Connection => write!(f, "connection"),
+            Policy::Interval(minutes) => write!(f, "interval:{}", minutes),
+            Policy::Deny => write!(f, "deny"),
         }
     }
 }
 
+impl Policy {
+    /// how much policy restricts signing, strictest applies when several tags of a key set one
+    pub fn strictness(&self) -> (u8, i64) {
+        match self {
+            Policy::Never => (0, 0),
+            Policy::Interval(minutes) => (1, -i64::from(*minutes)),
+            Policy::Connection => (2, 0),
+            Policy::Ask => (3, 0),
+            Policy::Deny => (4, 0),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ask" => Policy::Ask,
+            "never" => Policy::Never,
+            "connection" => Policy::Connection,
+            "deny" => Policy::Deny,
+            _ => match s.strip_prefix("interval:") {
+                Some(minutes) => match minutes.parse::<u32>()? {
+                    0 => anyhow::bail!("policy interval must be positive"),
+                    minutes => Policy::Interval(minutes),
+                },
+                None => anyhow::bail!(
+                    "unknown policy {}, expect ask, never, connection, interval:<minutes> or deny",
+                    s
+                ),
+            },
+        })
+    }
+}
+
+/// agent operation recorded for audit
+#[derive(Debug, Clone, sqlx::FromRow, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEvent {
+    pub id: i64,
+
+    /// unix time
+    pub time: i64,
+
+    /// `list`, `add`, `remove`, `remove-all` or `sign`
+    pub operation: String,
+    pub fingerprint: Option<String>,
+
+    /// `approved`, `denied`, `timed out` or `failed`, none when request succeeded
+    /// without approval
+    pub outcome: Option<String>,
+
+    pub peer_pid: Option<i64>,
+    pub peer_uid: Option<i64>,
+    pub peer_exe: Option<String>,
+
+    /// decoded sign purpose and destination
+    pub context: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct Key {
     pub item: KeyItem,
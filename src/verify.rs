@@ -0,0 +1,147 @@
+use bytes::{Bytes, BytesMut};
+use libsshkey::SSHBuffer;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+
+/// verify ssh signature blob of `data` against public key blob.
+///
+/// used to check host key signatures from client, so only host key algorithms are supported.
+pub fn verify_signature(
+    key_blob: impl AsRef<[u8]>,
+    data: impl AsRef<[u8]>,
+    signature: impl AsRef<[u8]>,
+) -> anyhow::Result<bool> {
+    let mut key = buffer(key_blob)?;
+    let mut sig = buffer(signature)?;
+
+    let key_type = string(key.get_string()?)?;
+    let sig_type = string(sig.get_string()?)?;
+    let sig = sig.get_string()?;
+    let data = data.as_ref();
+
+    match &*key_type {
+        "ssh-ed25519" => {
+            if sig_type != key_type {
+                anyhow::bail!("signature type {} mismatch key type", sig_type);
+            }
+
+            let public = key.get_string()?;
+            let pkey = PKey::public_key_from_raw_bytes(&public, Id::ED25519)?;
+            let mut verifier = Verifier::new_without_digest(&pkey)?;
+
+            Ok(verifier.verify_oneshot(&sig, data)?)
+        }
+        "ssh-rsa" => {
+            let digest = match &*sig_type {
+                "rsa-sha2-256" => MessageDigest::sha256(),
+                "rsa-sha2-512" => MessageDigest::sha512(),
+                "ssh-rsa" => MessageDigest::sha1(),
+                _ => anyhow::bail!("signature type {} mismatch key type", sig_type),
+            };
+
+            let e = BigNum::from_slice(&key.get_string()?)?;
+            let n = BigNum::from_slice(&key.get_string()?)?;
+            let pkey = PKey::from_rsa(Rsa::from_public_components(n, e)?)?;
+
+            verify_digest(digest, &pkey, data, &sig)
+        }
+        ty if ty.starts_with("ecdsa-sha2-") => {
+            if sig_type != key_type {
+                anyhow::bail!("signature type {} mismatch key type", sig_type);
+            }
+
+            let (nid, digest) = match ty {
+                "ecdsa-sha2-nistp256" => (Nid::X9_62_PRIME256V1, MessageDigest::sha256()),
+                "ecdsa-sha2-nistp384" => (Nid::SECP384R1, MessageDigest::sha384()),
+                "ecdsa-sha2-nistp521" => (Nid::SECP521R1, MessageDigest::sha512()),
+                _ => anyhow::bail!("unsupported curve: {}", ty),
+            };
+
+            let _curve = key.get_string()?;
+            let group = EcGroup::from_curve_name(nid)?;
+            let mut ctx = BigNumContext::new()?;
+            let point = EcPoint::from_bytes(&group, &key.get_string()?, &mut ctx)?;
+            let pkey = PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?;
+
+            let mut sig = buffer(sig)?;
+            let r = BigNum::from_slice(&sig.get_string()?)?;
+            let s = BigNum::from_slice(&sig.get_string()?)?;
+            let der = EcdsaSig::from_private_components(r, s)?.to_der()?;
+
+            verify_digest(digest, &pkey, data, &der)
+        }
+        _ => anyhow::bail!("unsupported key type: {}", key_type),
+    }
+}
+
+fn verify_digest(
+    digest: MessageDigest,
+    pkey: &PKey<Public>,
+    data: &[u8],
+    sig: &[u8],
+) -> anyhow::Result<bool> {
+    let mut verifier = Verifier::new(digest, pkey)?;
+    verifier.update(data)?;
+
+    Ok(verifier.verify(sig)?)
+}
+
+fn buffer(input: impl AsRef<[u8]>) -> anyhow::Result<SSHBuffer> {
+    Ok(SSHBuffer::from_bytes_mut(BytesMut::from(input.as_ref()))?)
+}
+
+fn string(input: Bytes) -> anyhow::Result<String> {
+    Ok(String::from_utf8(input.to_vec())?)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{Buf, BytesMut};
+    use libsshkey::SSHBuffer;
+
+    use crate::verify::verify_signature;
+
+    const SIG_ED25519: &str = "U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgUmF4el0VbDgMA9ya6lpQDymm7YvsW1X1ylkFSI4HhMUAAAAEZmlsZQAAAAAAAAAGc2hhNTEyAAAAUwAAAAtzc2gtZWQyNTUxOQAAAEDImcCivS04UE0cNhp2+ZR2XC7omEmJ1/mZr7Igsw7q1nk2dS3QtpyehQ8pZhr14Z6HaXSPDdIMQsD+VLtlS7MF";
+    const SIG_ECDSA: &str = "U1NIU0lHAAAAAQAAAGgAAAATZWNkc2Etc2hhMi1uaXN0cDI1NgAAAAhuaXN0cDI1NgAAAEEEChSEJxJmuxXpnMAW+WQXNugScWYQ148NDwECrMWQ7QHgG+WLkU1GWnPNw4o07Bm/mEhvf3kY3DUebv9qRDcHYAAAAARmaWxlAAAAAAAAAAZzaGE1MTIAAABkAAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAABJAAAAIDfBcnUHuTzjtxFUSP7xL3n1d+N498iT93oSBsc8vKsFAAAAIQD0i5m3k7Ie2w/XZPBovomiktjIp+AfaXOuqcdTpUBSzA==";
+    const SIG_RSA: &str = "U1NIU0lHAAAAAQAAAZcAAAAHc3NoLXJzYQAAAAMBAAEAAAGBALvwuZLZG7GCyBn5i/tHzSonK6ze82xAvkV7k+U3kkRXclKp+0brtZv2N9DuXyTP/Kmc/V59m2+b36JXOpiXuAtE2evGduTw2VyfkXxOjuZawWRRpZD/NEsc2WUYblkegQH/w0NJJ+iY1/tcjx/XavyzSpEFx/KdS8zhX2ma/L+sX62aRGYjGg3ESbNDtHP0O/uFQxLyB6lHJEm4QiKyqeqKqGphY927kNzOMBLY2O4NjLi8Kwm99VShYyu+006gtDWIA8eRxbAW0xgIX33VozH4ilPDAIe8TIsyeL3wEF3cj9I351ya/taiEix3RVxXjP2PXpWI1LC9vX+hn0zn3aatyKTejlykpThIFh+45QwxrYlfpj7F5EnwA7232F8yKM2bHzAMrw3W4fcFH1cZlgQfKDR3O80RKXjLL97pfQxwDfeE87jZ3rW3Z5cPWtp80gfbe5+tFJ6q3gkkLSka0xjI7yefvzU5F7pDuoxihjzghyzcoulHBoL75j4INByUgQAAAARmaWxlAAAAAAAAAAZzaGE1MTIAAAGUAAAADHJzYS1zaGEyLTUxMgAAAYCALysdfV3Co0D/99DefAzs/XaGHjVZm31cHfnEi3yblgaRAwleiJ09a4I8HO6TJUVSGosj5I6I+vFSN+NmTb5auypqSOknMtdsxKTnFXwkdjN2bnvLvSUZnOPGOH5mqplI04jaO2VcaPIDNSYRWu0ylWvlULIsaE3Ud4G0ajvxf42zVuQ2sp+WdKGhlbPwl+IPo9feiUo3YaJ6m6zATHKpK4r2XjO/kER669bgu4XhqS8eEj8LJwDTKge4zrPS/oLAbT/3PR7vgI7JfgXk82BZrxXiIGxRCToPnGvKD0YioXuWfP/2Indp8tWvc5cPCR5H0ZHXZfL9Qc3dt0Pt2wn9rZClaDY/FP9gENc/j+G8TPox2PPIG8hsMdgRuxgI7HB6EMd0gdGgPl7330EKSX1EE4HM1neVRupsItCFLqfuVLx5MAeVxAyGvbGnUU34cQpfPJUSWt49tOJ7EVtgTcAB1e1BEf9yIY9jRW/WiAT0YA0AUDUfrFgI5Us1EtCbENU=";
+
+    /// check `ssh-keygen -Y sign -n file` signature of "hello keyd"
+    fn check_sshsig(armored: &str) {
+        let blob = openssl::base64::decode_block(armored).unwrap();
+        assert_eq!(&blob[0..6], b"SSHSIG");
+
+        let mut buf = SSHBuffer::from_bytes_mut(BytesMut::from(&blob[6..])).unwrap();
+        let _version = buf.get_u32();
+        let public = buf.get_string().unwrap();
+        let namespace = buf.get_string().unwrap();
+        let reserved = buf.get_string().unwrap();
+        let hash_alg = buf.get_string().unwrap();
+        let signature = buf.get_string().unwrap();
+
+        let mut signed = SSHBuffer::empty().unwrap();
+        signed.put_string(&*namespace).unwrap();
+        signed.put_string(&*reserved).unwrap();
+        signed.put_string(&*hash_alg).unwrap();
+        signed
+            .put_string(&openssl::sha::sha512(b"hello keyd")[..])
+            .unwrap();
+        let mut data = b"SSHSIG".to_vec();
+        data.extend(signed.to_vec());
+
+        assert!(verify_signature(&public, &data, &signature).unwrap());
+        assert!(!verify_signature(&public, b"tampered", &signature).unwrap());
+    }
+
+    #[test]
+    fn verify_host_key_types() {
+        check_sshsig(SIG_ED25519);
+        check_sshsig(SIG_ECDSA);
+        check_sshsig(SIG_RSA);
+    }
+}